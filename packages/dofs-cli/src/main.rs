@@ -2,15 +2,20 @@ use fuser::{MountOption};
 use ctrlc;
 use std::process::Command;
 use std::fs;
+use std::thread;
 use log::info;
 use simplelog::*;
 mod fusefs;
 mod providers;
+mod ninep;
 use fusefs::FuseFS;
 use providers::memory::MemoryProvider;
 use providers::sqlite_simple::SqliteProvider as SqliteSimpleProvider;
-use providers::sqlite_chunked::SqliteChunkedProvider;
+use providers::sqlite_chunked::{SqliteChunkedProvider, WritesetEntry};
+use providers::fat::FatProvider;
 use clap::{Parser, Subcommand};
+use prettytable::{Table, Row, Cell};
+use rusqlite::{backup::Backup, Connection as RusqliteConnection};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -33,6 +38,41 @@ enum Commands {
         mountpoint: String,
         #[arg(long, default_value = "")]
         db_path: String,
+        /// Name of the environment variable holding the SQLCipher passphrase,
+        /// required when `--provider sqlite_encrypted` is used.
+        #[arg(long, default_value = "")]
+        key_env: String,
+        /// Also export the mounted tree over 9P2000.L, in addition to FUSE.
+        #[arg(long, default_value_t = false)]
+        mode_9p: bool,
+        /// Address the 9P server listens on when `--mode-9p` is set.
+        #[arg(long, default_value = "127.0.0.1:5640")]
+        listen: String,
+        /// SQLite journal mode for the `sqlite_chunked`/`sqlite_encrypted` providers
+        #[arg(long, default_value = "wal")]
+        journal: String,
+        /// SQLite busy timeout in milliseconds, so transient lock contention
+        /// from concurrent readers (stats/backup) retries instead of erroring
+        #[arg(long, default_value_t = 5000)]
+        busy_timeout_ms: u64,
+        /// Number of dedicated read-only connections the sqlite_chunked
+        /// provider keeps pooled for `lookup`/`getattr`/`readdir`, so those
+        /// calls proceed concurrently with an in-flight write instead of
+        /// queuing behind it. 0 disables the pool
+        #[arg(long, default_value_t = 4)]
+        read_pool_size: usize,
+        /// Record every mutation as a SQLite session changeset, appended to
+        /// this sidecar file, for later replay via `Commands::Apply`
+        #[arg(long)]
+        record_changes: Option<String>,
+        /// Compress new chunk data before storing it in the sqlite_chunked /
+        /// sqlite_encrypted providers: "none" (default), "zstd", or "lz4"
+        #[arg(long, default_value = "none")]
+        compress: String,
+        /// Mount the sqlite_chunked/sqlite_encrypted tree read-only as it
+        /// looked at this snapshot era instead of live
+        #[arg(long)]
+        read_snapshot: Option<u64>,
     },
     /// List available providers
     ListProviders,
@@ -40,6 +80,73 @@ enum Commands {
     Stats {
         #[arg(long, default_value = "")]
         db_path: String,
+        /// Output format
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+    /// Check (and optionally repair) a sqlite_chunked database for consistency
+    Check {
+        #[arg(long)]
+        db_path: String,
+        /// Fix inconsistencies instead of just reporting them
+        #[arg(long, default_value_t = false)]
+        repair: bool,
+    },
+    /// Take a consistent online backup of a live SQLite-backed database
+    /// without requiring an unmount
+    Backup {
+        #[arg(long, default_value = "memory")]
+        provider: String,
+        #[arg(long)]
+        db_path: String,
+        #[arg(long)]
+        out_path: String,
+        /// Pages copied per step before yielding, so a concurrently-mounted
+        /// filesystem keeps serving FUSE requests while the backup runs
+        #[arg(long, default_value_t = 100)]
+        pages_per_step: i32,
+    },
+    /// Take an era snapshot of a sqlite_chunked database, list past
+    /// snapshots, or list what changed since an earlier era
+    Snapshot {
+        #[arg(long)]
+        db_path: String,
+        /// Take a new snapshot and print its era instead of exporting
+        #[arg(long, default_value_t = false)]
+        snapshot: bool,
+        /// Optional human-readable label to attach to a new snapshot
+        /// (only meaningful with `--snapshot`)
+        #[arg(long)]
+        label: Option<String>,
+        /// List every snapshot taken so far instead of exporting
+        #[arg(long, default_value_t = false)]
+        list: bool,
+        /// Print the writeset (chunks + tombstones) since this era
+        #[arg(long)]
+        export_since: Option<u64>,
+    },
+    /// Replay a changeset captured via `Mount --record-changes` onto another
+    /// sqlite_chunked database, reconstructing its filesystem state
+    Apply {
+        #[arg(long)]
+        db_path: String,
+        #[arg(long)]
+        changeset: String,
+    },
+    /// Import an ISO9660 disc image into a fresh sqlite_chunked database,
+    /// recreating its directory tree and file contents
+    ImportIso9660 {
+        #[arg(long)]
+        db_path: String,
+        #[arg(long)]
+        iso_path: String,
+        /// Which naming scheme to read directory entries from when more than
+        /// one is present on the disc: "rock-ridge" (default), "joliet" or
+        /// "plain" — mirrors the `-9`/`-J`/`-R` flags in 9660srv. Whichever
+        /// is picked here is tried first; the other schemes are still used
+        /// as a fallback in their usual priority order.
+        #[arg(long, default_value = "rock-ridge")]
+        names: String,
     },
 }
 
@@ -48,7 +155,16 @@ fn main() {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Mount { provider, mode_osx, chunk_size, mountpoint, db_path } => {
+        Commands::Mount { provider, mode_osx, chunk_size, mountpoint, db_path, key_env, mode_9p, listen, journal, busy_timeout_ms, read_pool_size, record_changes, compress, read_snapshot } => {
+            let compression = match compress.as_str() {
+                "none" => None,
+                "zstd" => Some(providers::sqlite_chunked::CODEC_ZSTD),
+                "lz4" => Some(providers::sqlite_chunked::CODEC_LZ4),
+                other => {
+                    eprintln!("Unknown --compress codec '{}', expected one of: none, zstd, lz4", other);
+                    std::process::exit(1);
+                }
+            };
             let provider_name = provider.as_str();
             let osx_mode = mode_osx;
             let mountpoint = mountpoint.as_str();
@@ -94,14 +210,52 @@ fn main() {
                 "sqlite_chunked" => {
                     println!("Using SQLite Chunked provider");
                     let db_file = db_path.unwrap_or("cf-fuse-chunked.db");
-                    let sqlite = SqliteChunkedProvider::new_with_mode(db_file, osx_mode, chunk_size).expect("Failed to open SQLite DB");
+                    let mut sqlite = SqliteChunkedProvider::new_with_opts(db_file, osx_mode, chunk_size, &journal, busy_timeout_ms, read_pool_size).expect("Failed to open SQLite DB");
+                    sqlite.record_changes_path = record_changes.clone();
+                    sqlite.compression = compression;
+                    sqlite.read_snapshot = read_snapshot;
+                    if read_snapshot.is_some() {
+                        println!("Mounting read-only at snapshot era {}", read_snapshot.unwrap());
+                    }
+                    FuseFS::new(Box::new(sqlite))
+                },
+                "sqlite_encrypted" => {
+                    println!("Using SQLite Encrypted provider");
+                    if key_env.is_empty() {
+                        eprintln!("--key-env is required for --provider sqlite_encrypted");
+                        std::process::exit(1);
+                    }
+                    let db_file = db_path.unwrap_or("cf-fuse-encrypted.db");
+                    let mut sqlite = SqliteChunkedProvider::new_encrypted(db_file, &key_env, osx_mode, chunk_size)
+                        .expect("Failed to open encrypted SQLite DB (wrong passphrase?)");
+                    sqlite.compression = compression;
+                    sqlite.read_snapshot = read_snapshot;
+                    if read_snapshot.is_some() {
+                        println!("Mounting read-only at snapshot era {}", read_snapshot.unwrap());
+                    }
                     FuseFS::new(Box::new(sqlite))
                 },
+                "fat" => {
+                    println!("Using FAT image provider");
+                    let image_file = db_path.unwrap_or("cf-fuse.img");
+                    let fat = FatProvider::new_with_mode(image_file, osx_mode, 4 * 1024 * 1024).expect("Failed to open FAT image");
+                    FuseFS::new(Box::new(fat))
+                },
                 _ => {
                     println!("Using memory provider");
                     FuseFS::new(Box::new(MemoryProvider::new_with_mode(osx_mode)))
                 }
             };
+            if mode_9p {
+                let root = std::path::PathBuf::from(mountpoint);
+                let addr = listen.clone();
+                thread::spawn(move || {
+                    let tcp_listener = std::net::TcpListener::bind(&addr).expect("failed to bind 9P listener");
+                    if let Err(e) = ninep::serve(root, tcp_listener) {
+                        eprintln!("9P server error: {}", e);
+                    }
+                });
+            }
             info!("Mounting FS at {} with provider {}", mountpoint, provider_name);
             fuser::mount2(fs, mountpoint, &[MountOption::FSName(format!("{}fs", provider_name)), MountOption::AutoUnmount]).unwrap();
         },
@@ -110,14 +264,158 @@ fn main() {
             println!("  memory         - In-memory storage (default)");
             println!("  sqlite_simple  - Simple SQLite storage");
             println!("  sqlite_chunked - Chunked SQLite storage");
+            println!("  sqlite_encrypted - Chunked SQLite storage, encrypted at rest via SQLCipher (requires --key-env)");
+            println!("  fat            - FAT12/16/32 disk-image storage");
         },
-        Commands::Stats { db_path } => {
+        Commands::Stats { db_path, format } => {
             if db_path.is_empty() {
                 println!("Please specify a database path with --db-path");
                 return;
             }
-            println!("Stats for database: {}", db_path);
-            // TODO: Implement stats command
+            let provider = SqliteChunkedProvider::new(&db_path, None).expect("Failed to open SQLite DB");
+            let stats = provider.stats();
+            if format == "json" {
+                println!(
+                    "{{\"file_count\":{},\"dir_count\":{},\"total_logical_bytes\":{},\"chunk_count\":{},\"avg_chunk_fill_ratio\":{},\"db_size_bytes\":{},\"reclaimable_bytes\":{}}}",
+                    stats.file_count,
+                    stats.dir_count,
+                    stats.total_logical_bytes,
+                    stats.chunk_count,
+                    stats.avg_chunk_fill_ratio.map(|r| r.to_string()).unwrap_or("null".to_string()),
+                    stats.db_size_bytes(),
+                    stats.reclaimable_bytes(),
+                );
+            } else {
+                println!("Stats for database: {}", db_path);
+                println!("  files:               {}", stats.file_count);
+                println!("  directories:         {}", stats.dir_count);
+                println!("  total logical bytes: {}", stats.total_logical_bytes);
+                println!("  chunks:              {}", stats.chunk_count);
+                match stats.avg_chunk_fill_ratio {
+                    Some(ratio) => println!("  avg chunk fill:      {:.1}%", ratio * 100.0),
+                    None => println!("  avg chunk fill:      n/a"),
+                }
+                println!("  on-disk size:        {} bytes ({} pages x {} bytes)", stats.db_size_bytes(), stats.page_count, stats.page_size);
+                println!("  reclaimable (free):  {} bytes", stats.reclaimable_bytes());
+            }
+        },
+        Commands::Check { db_path, repair } => {
+            let mut provider = SqliteChunkedProvider::new(&db_path, None).expect("Failed to open SQLite DB");
+            let issues = provider.fsck(repair);
+            if issues.is_empty() {
+                println!("{}: no inconsistencies found", db_path);
+                return;
+            }
+            let mut table = Table::new();
+            table.add_row(Row::new(vec![Cell::new("kind"), Cell::new("detail")]));
+            for issue in &issues {
+                table.add_row(Row::new(vec![Cell::new(&issue.kind), Cell::new(&issue.detail)]));
+            }
+            table.printstd();
+            if repair {
+                let repaired = issues.iter().filter(|issue| issue.kind != "chunk_gap").count();
+                println!("{} issue(s) repaired", repaired);
+            } else {
+                println!("{} issue(s) found; re-run with --repair to fix", issues.len());
+            }
+        },
+        Commands::Apply { db_path, changeset } => {
+            let bytes = fs::read(&changeset).expect("Failed to read changeset file");
+            let conn = RusqliteConnection::open(&db_path).expect("Failed to open destination database");
+            let mut offset = 0usize;
+            let mut applied = 0u64;
+            while offset + 8 <= bytes.len() {
+                let len = u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize;
+                offset += 8;
+                if offset + len > bytes.len() {
+                    break;
+                }
+                let record = &bytes[offset..offset + len];
+                offset += len;
+                conn.apply_strm(
+                    &mut std::io::Cursor::new(record),
+                    None::<fn(&str) -> bool>,
+                    |_conflict_type, _item| rusqlite::session::ConflictAction::SqliteChangesetReplace,
+                ).expect("Failed to apply changeset record");
+                applied += 1;
+            }
+            println!("{}: applied {} changeset record(s) from {}", db_path, applied, changeset);
+        },
+        Commands::ImportIso9660 { db_path, iso_path, names } => {
+            use providers::iso9660::NameSource;
+            let first = match names.as_str() {
+                "joliet" => NameSource::Joliet,
+                "plain" => NameSource::Iso9660,
+                _ => NameSource::RockRidge,
+            };
+            let mut prefer = vec![first];
+            for src in [NameSource::RockRidge, NameSource::Joliet, NameSource::Iso9660] {
+                if src != first {
+                    prefer.push(src);
+                }
+            }
+            let stats = SqliteChunkedProvider::import_iso9660(&db_path, &iso_path, providers::iso9660::ImportOpts { prefer })
+                .expect("Failed to import ISO9660 image");
+            println!("{}: imported {} ({} dirs, {} files, {} bytes)", iso_path, db_path, stats.dirs, stats.files, stats.bytes);
+        },
+        Commands::Backup { provider, db_path, out_path, pages_per_step } => {
+            if provider == "memory" {
+                eprintln!("Nothing to back up: the memory provider keeps no on-disk database");
+                std::process::exit(1);
+            }
+            let src = RusqliteConnection::open(&db_path).expect("Failed to open source database");
+            let mut dst = RusqliteConnection::open(&out_path).expect("Failed to open backup destination");
+            let backup = Backup::new(&src, &mut dst).expect("Failed to start backup");
+            loop {
+                let progress = backup.step(pages_per_step).expect("Backup step failed");
+                info!("backup: {} pages remaining", progress.remaining);
+                if progress.remaining == 0 {
+                    break;
+                }
+                thread::sleep(std::time::Duration::from_millis(50));
+            }
+            println!("{}: backed up to {}", db_path, out_path);
+        },
+        Commands::Snapshot { db_path, snapshot, label, list, export_since } => {
+            let mut provider = SqliteChunkedProvider::new(&db_path, None).expect("Failed to open SQLite DB");
+            if list {
+                let snapshots = provider.list_snapshots();
+                if snapshots.is_empty() {
+                    println!("{}: no snapshots taken", db_path);
+                    return;
+                }
+                let mut table = Table::new();
+                table.add_row(Row::new(vec![Cell::new("era"), Cell::new("label"), Cell::new("created_at")]));
+                for (era, label, created_at) in &snapshots {
+                    table.add_row(Row::new(vec![Cell::new(&era.to_string()), Cell::new(label.as_deref().unwrap_or("-")), Cell::new(&created_at.to_string())]));
+                }
+                table.printstd();
+            } else if let Some(since) = export_since {
+                let entries = provider.changed_since(since);
+                if entries.is_empty() {
+                    println!("{}: nothing changed since era {}", db_path, since);
+                    return;
+                }
+                let mut table = Table::new();
+                table.add_row(Row::new(vec![Cell::new("kind"), Cell::new("ino"), Cell::new("offset"), Cell::new("bytes")]));
+                for entry in &entries {
+                    match entry {
+                        WritesetEntry::Chunk { ino, offset, data } => {
+                            table.add_row(Row::new(vec![Cell::new("chunk"), Cell::new(&ino.to_string()), Cell::new(&offset.to_string()), Cell::new(&data.len().to_string())]));
+                        },
+                        WritesetEntry::Tombstone { ino, offset } => {
+                            table.add_row(Row::new(vec![Cell::new("tombstone"), Cell::new(&ino.to_string()), Cell::new(&offset.to_string()), Cell::new("-")]));
+                        },
+                    }
+                }
+                table.printstd();
+                println!("{} record(s) changed since era {}", entries.len(), since);
+            } else if snapshot {
+                let era = provider.create_snapshot(label.as_deref());
+                println!("{}: snapshot taken at era {}", db_path, era);
+            } else {
+                println!("Specify --snapshot to take a snapshot, --list to list past snapshots, or --export-since <era> to list changes");
+            }
         },
     }
 }
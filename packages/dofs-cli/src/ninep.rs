@@ -0,0 +1,438 @@
+//! Minimal 9P2000.L server that re-exports an already-mounted FUSE tree over
+//! a TCP socket, so a VM or remote client can attach to it the way
+//! a normal p9 server works. The server walks the *mounted* directory with
+//! plain `std::fs` calls rather than talking to a `Provider` directly: once
+//! `--mode-osx`/`--provider` has mounted the tree, the mountpoint already is
+//! the provider-backed filesystem, so serving it over 9P is just another
+//! transport in front of the same data.
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+const MSIZE: u32 = 8192;
+
+const TVERSION: u8 = 100;
+const RVERSION: u8 = 101;
+const TATTACH: u8 = 104;
+const RATTACH: u8 = 105;
+const RLERROR: u8 = 7;
+const TWALK: u8 = 110;
+const RWALK: u8 = 111;
+const TLOPEN: u8 = 12;
+const RLOPEN: u8 = 13;
+const TLCREATE: u8 = 14;
+const RLCREATE: u8 = 15;
+const TREADDIR: u8 = 40;
+const RREADDIR: u8 = 41;
+const TGETATTR: u8 = 24;
+const RGETATTR: u8 = 25;
+const TSETATTR: u8 = 26;
+const RSETATTR: u8 = 27;
+const TREAD: u8 = 116;
+const RREAD: u8 = 117;
+const TWRITE: u8 = 118;
+const RWRITE: u8 = 119;
+const TCLUNK: u8 = 120;
+const RCLUNK: u8 = 121;
+const TREMOVE: u8 = 122;
+const RREMOVE: u8 = 123;
+
+const QTDIR: u8 = 0x80;
+const QTFILE: u8 = 0x00;
+
+/// A single fid's state: the path it walked to, plus an open file handle and
+/// directory-listing cursor once `Tlopen`/`Treaddir` have been issued.
+struct Fid {
+    path: PathBuf,
+    file: Option<File>,
+    dir_entries: Option<Vec<(String, PathBuf)>>,
+}
+
+struct Conn {
+    root: PathBuf,
+    fids: HashMap<u32, Fid>,
+}
+
+fn qid_for(path: &Path) -> io::Result<(u8, u64)> {
+    let meta = fs::symlink_metadata(path)?;
+    let qtype = if meta.is_dir() { QTDIR } else { QTFILE };
+    Ok((qtype, meta.ino()))
+}
+
+// --- Wire-format primitives: 9P uses little-endian fixed-width ints, 2-byte
+// length-prefixed UTF-8 strings, and 4-byte length-prefixed byte blobs. ---
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+    fn u8(&mut self) -> u8 {
+        let v = self.buf[self.pos];
+        self.pos += 1;
+        v
+    }
+    fn u16(&mut self) -> u16 {
+        let v = u16::from_le_bytes(self.buf[self.pos..self.pos + 2].try_into().unwrap());
+        self.pos += 2;
+        v
+    }
+    fn u32(&mut self) -> u32 {
+        let v = u32::from_le_bytes(self.buf[self.pos..self.pos + 4].try_into().unwrap());
+        self.pos += 4;
+        v
+    }
+    fn u64(&mut self) -> u64 {
+        let v = u64::from_le_bytes(self.buf[self.pos..self.pos + 8].try_into().unwrap());
+        self.pos += 8;
+        v
+    }
+    fn string(&mut self) -> String {
+        let len = self.u16() as usize;
+        let s = String::from_utf8_lossy(&self.buf[self.pos..self.pos + len]).into_owned();
+        self.pos += len;
+        s
+    }
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let s = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        s
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+    fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    fn string(&mut self, s: &str) {
+        self.u16(s.len() as u16);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+    fn qid(&mut self, qtype: u8, path: u64) {
+        self.u8(qtype);
+        self.u32(0); // version
+        self.u64(path);
+    }
+}
+
+fn write_message(stream: &mut TcpStream, msg_type: u8, tag: u16, body: &Writer) -> io::Result<()> {
+    let size = 4 + 1 + 2 + body.buf.len() as u32;
+    let mut out = Vec::with_capacity(size as usize);
+    out.extend_from_slice(&size.to_le_bytes());
+    out.push(msg_type);
+    out.extend_from_slice(&tag.to_le_bytes());
+    out.extend_from_slice(&body.buf);
+    stream.write_all(&out)
+}
+
+fn write_error(stream: &mut TcpStream, tag: u16, errno: i32) -> io::Result<()> {
+    let mut w = Writer::default();
+    w.u32(errno as u32);
+    write_message(stream, RLERROR, tag, &w)
+}
+
+fn read_message(stream: &mut TcpStream) -> io::Result<Option<(u8, u16, Vec<u8>)>> {
+    let mut size_buf = [0u8; 4];
+    if let Err(e) = stream.read_exact(&mut size_buf) {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            return Ok(None);
+        }
+        return Err(e);
+    }
+    let size = u32::from_le_bytes(size_buf);
+    let mut rest = vec![0u8; size as usize - 4];
+    stream.read_exact(&mut rest)?;
+    let msg_type = rest[0];
+    let tag = u16::from_le_bytes([rest[1], rest[2]]);
+    let body = rest[3..].to_vec();
+    Ok(Some((msg_type, tag, body)))
+}
+
+impl Conn {
+    fn handle(&mut self, stream: &mut TcpStream, msg_type: u8, tag: u16, body: Vec<u8>) -> io::Result<()> {
+        let mut r = Reader::new(&body);
+        match msg_type {
+            TVERSION => {
+                let _msize = r.u32();
+                let version = r.string();
+                let mut w = Writer::default();
+                w.u32(MSIZE);
+                w.string(if version == "9P2000.L" { "9P2000.L" } else { "unknown" });
+                write_message(stream, RVERSION, tag, &w)
+            }
+            TATTACH => {
+                let fid = r.u32();
+                let _afid = r.u32();
+                let _uname = r.string();
+                let _aname = r.string();
+                let path = self.root.clone();
+                let (qtype, qpath) = qid_for(&path)?;
+                self.fids.insert(fid, Fid { path, file: None, dir_entries: None });
+                let mut w = Writer::default();
+                w.qid(qtype, qpath);
+                write_message(stream, RATTACH, tag, &w)
+            }
+            TWALK => {
+                let fid = r.u32();
+                let newfid = r.u32();
+                let nwname = r.u16();
+                let mut path = match self.fids.get(&fid) {
+                    Some(f) => f.path.clone(),
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                let mut w = Writer::default();
+                let mut wqids = Vec::new();
+                for _ in 0..nwname {
+                    let name = r.string();
+                    path.push(&name);
+                    match qid_for(&path) {
+                        Ok(q) => wqids.push(q),
+                        Err(_) => break,
+                    }
+                }
+                w.u16(wqids.len() as u16);
+                for (qtype, qpath) in &wqids {
+                    w.qid(*qtype, *qpath);
+                }
+                if wqids.len() as u16 == nwname {
+                    self.fids.insert(newfid, Fid { path, file: None, dir_entries: None });
+                }
+                write_message(stream, RWALK, tag, &w)
+            }
+            TLOPEN => {
+                let fid = r.u32();
+                let _flags = r.u32();
+                let path = match self.fids.get(&fid) {
+                    Some(f) => f.path.clone(),
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                let (qtype, qpath) = qid_for(&path)?;
+                if qtype != QTDIR {
+                    let file = OpenOptions::new().read(true).write(true).open(&path)?;
+                    if let Some(f) = self.fids.get_mut(&fid) {
+                        f.file = Some(file);
+                    }
+                }
+                let mut w = Writer::default();
+                w.qid(qtype, qpath);
+                w.u32(0); // iounit: let the client pick its own read/write size
+                write_message(stream, RLOPEN, tag, &w)
+            }
+            TLCREATE => {
+                let fid = r.u32();
+                let name = r.string();
+                let _flags = r.u32();
+                let _mode = r.u32();
+                let _gid = r.u32();
+                let parent = match self.fids.get(&fid) {
+                    Some(f) => f.path.clone(),
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                let path = parent.join(&name);
+                let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)?;
+                let (qtype, qpath) = qid_for(&path)?;
+                if let Some(f) = self.fids.get_mut(&fid) {
+                    f.path = path;
+                    f.file = Some(file);
+                }
+                let mut w = Writer::default();
+                w.qid(qtype, qpath);
+                w.u32(0);
+                write_message(stream, RLCREATE, tag, &w)
+            }
+            TREADDIR => {
+                let fid = r.u32();
+                let offset = r.u64();
+                let _count = r.u32();
+                let path = match self.fids.get(&fid) {
+                    Some(f) => f.path.clone(),
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                if offset == 0 || self.fids.get(&fid).map_or(true, |f| f.dir_entries.is_none()) {
+                    let mut entries = vec![(".".to_string(), path.clone())];
+                    if let Ok(rd) = fs::read_dir(&path) {
+                        for entry in rd.flatten() {
+                            entries.push((entry.file_name().to_string_lossy().into_owned(), entry.path()));
+                        }
+                    }
+                    if let Some(f) = self.fids.get_mut(&fid) {
+                        f.dir_entries = Some(entries);
+                    }
+                }
+                let mut w = Writer::default();
+                let dir_body_start = w.buf.len();
+                w.u32(0); // placeholder count, patched below
+                let mut total = 0usize;
+                if let Some(f) = self.fids.get(&fid) {
+                    if let Some(entries) = &f.dir_entries {
+                        for (i, (name, p)) in entries.iter().enumerate().skip(offset as usize) {
+                            let (qtype, qpath) = qid_for(p).unwrap_or((QTFILE, 0));
+                            let entry_len = 13 + 8 + 1 + 2 + name.len();
+                            if dir_body_start + entry_len + w.buf.len() - dir_body_start > MSIZE as usize {
+                                break;
+                            }
+                            w.qid(qtype, qpath);
+                            w.u64((i + 1) as u64);
+                            w.u8(if qtype == QTDIR { 4 } else { 8 }); // DT_DIR / DT_REG
+                            w.string(name);
+                            total += 1;
+                        }
+                    }
+                }
+                let count_bytes = (w.buf.len() - dir_body_start - 4) as u32;
+                w.buf[dir_body_start..dir_body_start + 4].copy_from_slice(&count_bytes.to_le_bytes());
+                let _ = total;
+                write_message(stream, RREADDIR, tag, &w)
+            }
+            TGETATTR => {
+                let fid = r.u32();
+                let _request_mask = r.u64();
+                let path = match self.fids.get(&fid) {
+                    Some(f) => f.path.clone(),
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                let meta = fs::symlink_metadata(&path)?;
+                let (qtype, qpath) = qid_for(&path)?;
+                let mut w = Writer::default();
+                w.u64(u64::MAX); // valid: report everything we filled in
+                w.qid(qtype, qpath);
+                w.u32(meta.mode());
+                w.u32(meta.uid());
+                w.u32(meta.gid());
+                w.u64(meta.nlink());
+                w.u64(meta.rdev());
+                w.u64(meta.size());
+                w.u64(meta.blksize());
+                w.u64(meta.blocks());
+                for _ in 0..8 {
+                    w.u64(0); // atime/mtime/ctime/btime sec+nsec pairs, truncated
+                }
+                w.u64(0); // gen
+                w.u64(0); // data_version
+                write_message(stream, RGETATTR, tag, &w)
+            }
+            TSETATTR => {
+                let fid = r.u32();
+                let valid = r.u32();
+                let _mode = r.u32();
+                let _uid = r.u32();
+                let _gid = r.u32();
+                let size = r.u64();
+                let path = match self.fids.get(&fid) {
+                    Some(f) => f.path.clone(),
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                const P9_SETATTR_SIZE: u32 = 0x08;
+                if valid & P9_SETATTR_SIZE != 0 {
+                    let file = OpenOptions::new().write(true).open(&path)?;
+                    file.set_len(size)?;
+                }
+                write_message(stream, RSETATTR, tag, &Writer::default())
+            }
+            TREAD => {
+                let fid = r.u32();
+                let offset = r.u64();
+                let count = r.u32();
+                let f = match self.fids.get_mut(&fid) {
+                    Some(f) => f,
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                let file = match &mut f.file {
+                    Some(file) => file,
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                file.seek(SeekFrom::Start(offset))?;
+                let mut data = vec![0u8; count as usize];
+                let n = file.read(&mut data).unwrap_or(0);
+                data.truncate(n);
+                let mut w = Writer::default();
+                w.u32(data.len() as u32);
+                w.buf.extend_from_slice(&data);
+                write_message(stream, RREAD, tag, &w)
+            }
+            TWRITE => {
+                let fid = r.u32();
+                let offset = r.u64();
+                let count = r.u32();
+                let data = r.bytes(count as usize).to_vec();
+                let f = match self.fids.get_mut(&fid) {
+                    Some(f) => f,
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                let file = match &mut f.file {
+                    Some(file) => file,
+                    None => return write_error(stream, tag, libc::EBADF),
+                };
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(&data)?;
+                let mut w = Writer::default();
+                w.u32(data.len() as u32);
+                write_message(stream, RWRITE, tag, &w)
+            }
+            TCLUNK => {
+                let fid = r.u32();
+                self.fids.remove(&fid);
+                write_message(stream, RCLUNK, tag, &Writer::default())
+            }
+            TREMOVE => {
+                let fid = r.u32();
+                if let Some(f) = self.fids.remove(&fid) {
+                    let meta = fs::symlink_metadata(&f.path)?;
+                    if meta.is_dir() {
+                        fs::remove_dir(&f.path)?;
+                    } else {
+                        fs::remove_file(&f.path)?;
+                    }
+                }
+                write_message(stream, RREMOVE, tag, &Writer::default())
+            }
+            other => write_error(stream, tag, libc::EOPNOTSUPP).and_then(|_| {
+                log::warn!("9P: unhandled message type {other}");
+                Ok(())
+            }),
+        }
+    }
+}
+
+/// Serves `root` (an already-mounted provider tree) to 9P2000.L clients
+/// accepted from `listener`. Handles one client connection at a time; a new
+/// connection replaces the previous fid table.
+pub fn serve(root: PathBuf, listener: TcpListener) -> io::Result<()> {
+    log::info!("9P server listening on {:?}, exporting {:?}", listener.local_addr()?, root);
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let mut conn = Conn { root: root.clone(), fids: HashMap::new() };
+        loop {
+            let (msg_type, tag, body) = match read_message(&mut stream)? {
+                Some(m) => m,
+                None => break,
+            };
+            if let Err(e) = conn.handle(&mut stream, msg_type, tag, body) {
+                log::warn!("9P: request failed: {e}");
+                let _ = write_error(&mut stream, tag, e.raw_os_error().unwrap_or(libc::EIO));
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+/// Below this many bytes into the current chunk, a boundary is never
+/// declared — this is what stops the chunker from producing a flood of
+/// tiny chunks on pathological input.
+const MIN_SIZE: usize = 2 * 1024;
+/// Past this point the chunker switches to the easier-to-satisfy mask, so a
+/// boundary becomes likelier and the chunk doesn't keep growing past it by
+/// much before one is found.
+const TARGET_SIZE: usize = 8 * 1024;
+/// A chunk is always cut here regardless of the rolling hash, bounding the
+/// worst case (all-zero input, a hash collision run, ...).
+const MAX_SIZE: usize = 64 * 1024;
+/// Stricter mask (more bits) used below `TARGET_SIZE`: lower match
+/// probability, so chunks are discouraged from ending early.
+const MASK_SMALL: u64 = (1 << 13) - 1;
+/// Looser mask (fewer bits) used from `TARGET_SIZE` to `MAX_SIZE`: higher
+/// match probability, nudging the chunk to end before it hits the hard cap.
+const MASK_LARGE: u64 = (1 << 11) - 1;
+
+/// Precomputed pseudo-random table for the "gear hash" FastCDC rolls over
+/// the input one byte at a time (`hash = hash << 1 + gear[byte]`). It only
+/// needs to be well-mixed, not cryptographically strong, so a small xorshift
+/// seeded from a fixed constant is enough — no need to vendor a real one.
+fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+    for slot in table.iter_mut() {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        *slot = seed;
+    }
+    table
+}
+
+/// Splits `data` into content-defined chunks, FastCDC-style: a rolling gear
+/// hash is advanced byte by byte and a boundary is declared once it matches
+/// a size-dependent mask, so a chunk boundary depends on a window of local
+/// content rather than a fixed offset — inserting or deleting bytes earlier
+/// in the file shifts later boundaries by the same amount instead of
+/// re-chunking everything after the edit, the property that makes
+/// content-addressed dedup actually pay off across similar files.
+///
+/// Returns `(offset, length)` pairs covering all of `data` in order.
+pub fn content_defined_chunks(data: &[u8]) -> Vec<(usize, usize)> {
+    let gear = gear_table();
+    let mut boundaries = Vec::new();
+    let mut start = 0usize;
+    let mut hash: u64 = 0;
+    let mut i = 0usize;
+    while i < data.len() {
+        let chunk_len = i - start + 1;
+        hash = (hash << 1).wrapping_add(gear[data[i] as usize]);
+        i += 1;
+        if chunk_len < MIN_SIZE {
+            continue;
+        }
+        let mask = if chunk_len < TARGET_SIZE { MASK_SMALL } else { MASK_LARGE };
+        if chunk_len >= MAX_SIZE || hash & mask == 0 {
+            boundaries.push((start, i - start));
+            start = i;
+            hash = 0;
+        }
+    }
+    if start < data.len() {
+        boundaries.push((start, data.len() - start));
+    }
+    boundaries
+}
@@ -0,0 +1,923 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::SystemTime;
+
+const BYTES_PER_SECTOR: usize = 512;
+const SECTORS_PER_CLUSTER: usize = 1;
+const RESERVED_SECTORS: usize = 1;
+const NUM_FATS: usize = 1;
+const ROOT_ENTRY_COUNT: usize = 512; // FAT12/16 fixed root directory, 32 bytes/entry
+const DIR_ENTRY_SIZE: usize = 32;
+const FAT32_EOC: u32 = 0x0FFF_FFFF;
+
+/// FUSE inode 1 is reserved for the filesystem root; every other inode is
+/// `first_cluster + 2` so cluster numbers (which start at 2 on FAT) map
+/// 1:1 onto inode numbers without a side table.
+const ROOT_INODE: u64 = 1;
+
+fn cluster_to_ino(cluster: u32) -> u64 {
+    cluster as u64 + 2
+}
+fn ino_to_cluster(ino: u64) -> u32 {
+    (ino - 2) as u32
+}
+
+/// Checks `req_uid`/`req_gid` against `attr`'s owner/group/other rwx bits,
+/// the standard POSIX rule the kernel would otherwise enforce itself under
+/// `-o default_permissions`. `mask` uses the `libc::{R,W,X}_OK` bits from
+/// `access(2)`. FAT has no notion of supplementary groups on disk, so unlike
+/// `SqliteChunkedProvider` this only checks the single `req_gid` the request
+/// carried.
+fn check_access(attr: &fuser::FileAttr, req_uid: u32, req_gid: u32, mask: i32) -> bool {
+    if mask == libc::F_OK || req_uid == 0 {
+        return true;
+    }
+    let bits = if req_uid == attr.uid {
+        (attr.perm >> 6) & 0o7
+    } else if req_gid == attr.gid {
+        (attr.perm >> 3) & 0o7
+    } else {
+        attr.perm & 0o7
+    } as i32;
+    bits & mask == mask
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FatBits {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// A FAT12/16/32 image file used as the sole backing store for the mounted
+/// tree: the boot sector, FAT table(s), root directory and data clusters
+/// all live inside one file, so the image can be copied out and mounted
+/// directly by another OS or VM.
+pub struct FatProvider {
+    file: File,
+    image: Vec<u8>,
+    bits: FatBits,
+    bytes_per_cluster: usize,
+    fat_size_sectors: u32,
+    total_clusters: u32,
+    root_dir_offset: usize,
+    root_dir_sectors: usize,
+    data_start_sector: usize,
+    pub osx_mode: bool,
+    /// Extended attributes keyed by `(ino, name)`. FAT12/16/32 has no
+    /// on-disk field to hold these, so they live only for the life of the
+    /// mount, same as a fresh `MemoryProvider`.
+    xattrs: HashMap<(u64, String), Vec<u8>>,
+}
+
+struct DirEntry {
+    long_name: String,
+    attr: u8,
+    first_cluster: u32,
+    size: u32,
+    /// Byte offset of the entry's short-name record, for in-place updates.
+    short_entry_offset: usize,
+}
+
+impl FatProvider {
+    #[allow(dead_code)]
+    pub fn new(image_path: &str, capacity_bytes: Option<u64>) -> std::io::Result<Self> {
+        Self::new_with_mode(image_path, false, capacity_bytes.unwrap_or(4 * 1024 * 1024))
+    }
+
+    pub fn new_with_mode(image_path: &str, osx_mode: bool, capacity_bytes: u64) -> std::io::Result<Self> {
+        let exists = std::path::Path::new(image_path).exists();
+        let file = OpenOptions::new().read(true).write(true).create(true).open(image_path)?;
+        let mut provider = if exists {
+            let mut f = file;
+            let mut image = Vec::new();
+            f.read_to_end(&mut image)?;
+            let mut provider = Self::from_image(f, image, osx_mode);
+            provider.file.seek(SeekFrom::Start(0))?;
+            provider
+        } else {
+            Self::format(file, capacity_bytes, osx_mode)?
+        };
+        provider.flush_image()?;
+        Ok(provider)
+    }
+
+    fn from_image(file: File, image: Vec<u8>, osx_mode: bool) -> Self {
+        let bytes_per_sector = u16::from_le_bytes([image[11], image[12]]) as usize;
+        let sectors_per_cluster = image[13] as usize;
+        let reserved_sectors = u16::from_le_bytes([image[14], image[15]]) as usize;
+        let num_fats = image[16] as usize;
+        let root_entry_count = u16::from_le_bytes([image[17], image[18]]) as usize;
+        let mut fat_size = u16::from_le_bytes([image[22], image[23]]) as u32;
+        if fat_size == 0 {
+            fat_size = u32::from_le_bytes([image[36], image[37], image[38], image[39]]);
+        }
+        let total_sectors16 = u16::from_le_bytes([image[19], image[20]]) as u32;
+        let total_sectors = if total_sectors16 != 0 {
+            total_sectors16
+        } else {
+            u32::from_le_bytes([image[32], image[33], image[34], image[35]])
+        };
+        let root_dir_sectors = (root_entry_count * DIR_ENTRY_SIZE + bytes_per_sector - 1) / bytes_per_sector;
+        let data_start_sector = reserved_sectors + num_fats * fat_size as usize + root_dir_sectors;
+        let total_clusters = (total_sectors as usize - data_start_sector) / sectors_per_cluster;
+        let bits = if total_clusters < 4085 {
+            FatBits::Fat12
+        } else if total_clusters < 65525 {
+            FatBits::Fat16
+        } else {
+            FatBits::Fat32
+        };
+        Self {
+            file,
+            image,
+            bits,
+            bytes_per_cluster: bytes_per_sector * sectors_per_cluster,
+            fat_size_sectors: fat_size,
+            total_clusters: total_clusters as u32,
+            root_dir_offset: (reserved_sectors + num_fats * fat_size as usize) * bytes_per_sector,
+            root_dir_sectors,
+            data_start_sector,
+            osx_mode,
+            xattrs: HashMap::new(),
+        }
+    }
+
+    fn format(mut file: File, capacity_bytes: u64, osx_mode: bool) -> std::io::Result<Self> {
+        let total_sectors = (capacity_bytes as usize / BYTES_PER_SECTOR).max(2048) as u32;
+        let root_dir_sectors = (ROOT_ENTRY_COUNT * DIR_ENTRY_SIZE + BYTES_PER_SECTOR - 1) / BYTES_PER_SECTOR;
+        // Conservative FAT16-shaped sizing: one FAT entry (2 bytes) per data
+        // cluster, sized for the whole image up front since the image is
+        // fixed-length and never grows.
+        let approx_clusters = total_sectors as usize / SECTORS_PER_CLUSTER;
+        let fat_size_sectors = ((approx_clusters * 2 + BYTES_PER_SECTOR - 1) / BYTES_PER_SECTOR).max(1) as u32;
+        let data_start_sector = RESERVED_SECTORS + NUM_FATS * fat_size_sectors as usize + root_dir_sectors;
+        let total_clusters = ((total_sectors as usize).saturating_sub(data_start_sector)) / SECTORS_PER_CLUSTER;
+
+        let mut image = vec![0u8; total_sectors as usize * BYTES_PER_SECTOR];
+        image[11..13].copy_from_slice(&(BYTES_PER_SECTOR as u16).to_le_bytes());
+        image[13] = SECTORS_PER_CLUSTER as u8;
+        image[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+        image[16] = NUM_FATS as u8;
+        image[17..19].copy_from_slice(&(ROOT_ENTRY_COUNT as u16).to_le_bytes());
+        if total_sectors <= u16::MAX as u32 {
+            image[19..21].copy_from_slice(&(total_sectors as u16).to_le_bytes());
+        } else {
+            image[32..36].copy_from_slice(&total_sectors.to_le_bytes());
+        }
+        image[21] = 0xF8; // media: fixed disk
+        image[22..24].copy_from_slice(&(fat_size_sectors as u16).to_le_bytes());
+        image[510] = 0x55;
+        image[511] = 0xAA;
+
+        // Reserve the first two FAT entries per spec (cluster 0/1 don't exist).
+        let fat_offset = RESERVED_SECTORS * BYTES_PER_SECTOR;
+        image[fat_offset] = 0xF8;
+        image[fat_offset + 1] = 0xFF;
+        image[fat_offset + 2] = 0xFF;
+
+        file.write_all(&image)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let bits = if total_clusters < 4085 {
+            FatBits::Fat12
+        } else if total_clusters < 65525 {
+            FatBits::Fat16
+        } else {
+            FatBits::Fat32
+        };
+        let provider = Self {
+            file,
+            image,
+            bits,
+            bytes_per_cluster: BYTES_PER_SECTOR * SECTORS_PER_CLUSTER,
+            fat_size_sectors,
+            total_clusters: total_clusters as u32,
+            root_dir_offset: (RESERVED_SECTORS + NUM_FATS * fat_size_sectors as usize) * BYTES_PER_SECTOR,
+            root_dir_sectors,
+            data_start_sector,
+            osx_mode,
+            xattrs: HashMap::new(),
+        };
+        Ok(provider)
+    }
+
+    fn flush_image(&mut self) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&self.image)?;
+        self.file.flush()
+    }
+
+    // --- FAT table ---
+
+    fn fat_offset(&self) -> usize {
+        RESERVED_SECTORS * BYTES_PER_SECTOR
+    }
+
+    fn read_fat_entry(&self, cluster: u32) -> u32 {
+        let base = self.fat_offset();
+        match self.bits {
+            FatBits::Fat12 => {
+                let off = base + (cluster as usize * 3) / 2;
+                let word = u16::from_le_bytes([self.image[off], self.image[off + 1]]);
+                if cluster % 2 == 0 { (word & 0x0FFF) as u32 } else { (word >> 4) as u32 }
+            }
+            FatBits::Fat16 => {
+                let off = base + cluster as usize * 2;
+                u16::from_le_bytes([self.image[off], self.image[off + 1]]) as u32
+            }
+            FatBits::Fat32 => {
+                let off = base + cluster as usize * 4;
+                u32::from_le_bytes([self.image[off], self.image[off + 1], self.image[off + 2], self.image[off + 3]]) & 0x0FFF_FFFF
+            }
+        }
+    }
+
+    fn write_fat_entry(&mut self, cluster: u32, value: u32) {
+        let base = self.fat_offset();
+        match self.bits {
+            FatBits::Fat12 => {
+                let off = base + (cluster as usize * 3) / 2;
+                let mut word = u16::from_le_bytes([self.image[off], self.image[off + 1]]);
+                if cluster % 2 == 0 {
+                    word = (word & 0xF000) | (value as u16 & 0x0FFF);
+                } else {
+                    word = (word & 0x000F) | ((value as u16) << 4);
+                }
+                self.image[off..off + 2].copy_from_slice(&word.to_le_bytes());
+            }
+            FatBits::Fat16 => {
+                let off = base + cluster as usize * 2;
+                self.image[off..off + 2].copy_from_slice(&(value as u16).to_le_bytes());
+            }
+            FatBits::Fat32 => {
+                let off = base + cluster as usize * 4;
+                let existing = u32::from_le_bytes([self.image[off], self.image[off + 1], self.image[off + 2], self.image[off + 3]]);
+                let packed = (existing & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                self.image[off..off + 4].copy_from_slice(&packed.to_le_bytes());
+            }
+        }
+    }
+
+    fn eoc_marker(&self) -> u32 {
+        match self.bits {
+            FatBits::Fat12 => 0x0FFF,
+            FatBits::Fat16 => 0xFFFF,
+            FatBits::Fat32 => FAT32_EOC,
+        }
+    }
+
+    fn is_eoc(&self, entry: u32) -> bool {
+        match self.bits {
+            FatBits::Fat12 => entry >= 0x0FF8,
+            FatBits::Fat16 => entry >= 0xFFF8,
+            FatBits::Fat32 => entry >= 0x0FFF_FFF8,
+        }
+    }
+
+    /// Scans the FAT for the first unused (zero) entry, the same free-space
+    /// scan every minimal FAT driver does since there's no free bitmap.
+    fn alloc_cluster(&mut self) -> Option<u32> {
+        for cluster in 2..self.total_clusters + 2 {
+            if self.read_fat_entry(cluster) == 0 {
+                self.write_fat_entry(cluster, self.eoc_marker());
+                return Some(cluster);
+            }
+        }
+        None
+    }
+
+    fn cluster_chain(&self, first_cluster: u32) -> Vec<u32> {
+        let mut chain = Vec::new();
+        let mut cluster = first_cluster;
+        while cluster != 0 && !self.is_eoc(cluster) && (chain.len() as u32) < self.total_clusters {
+            chain.push(cluster);
+            cluster = self.read_fat_entry(cluster);
+        }
+        chain
+    }
+
+    fn free_chain(&mut self, first_cluster: u32) {
+        if first_cluster == 0 {
+            return;
+        }
+        for cluster in self.cluster_chain(first_cluster) {
+            self.write_fat_entry(cluster, 0);
+        }
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> usize {
+        (self.data_start_sector + (cluster as usize - 2) * SECTORS_PER_CLUSTER) * BYTES_PER_SECTOR
+    }
+
+    fn read_chain_data(&self, first_cluster: u32, len: usize) -> Vec<u8> {
+        if first_cluster == 0 {
+            return Vec::new();
+        }
+        let mut data = Vec::with_capacity(len.min(self.bytes_per_cluster * 64));
+        for cluster in self.cluster_chain(first_cluster) {
+            let off = self.cluster_offset(cluster);
+            data.extend_from_slice(&self.image[off..off + self.bytes_per_cluster]);
+        }
+        data.truncate(len);
+        data
+    }
+
+    /// Rewrites a file's cluster chain from scratch: frees the old chain,
+    /// allocates as many clusters as the new content needs, and copies the
+    /// bytes in. Matches the "replace the whole blob" idiom the SQLite
+    /// providers in this crate use for writes rather than true in-place
+    /// incremental cluster patching.
+    fn write_chain_data(&mut self, first_cluster: u32, data: &[u8]) -> u32 {
+        self.free_chain(first_cluster);
+        if data.is_empty() {
+            return 0;
+        }
+        let clusters_needed = (data.len() + self.bytes_per_cluster - 1) / self.bytes_per_cluster;
+        let mut chain = Vec::with_capacity(clusters_needed);
+        for _ in 0..clusters_needed {
+            match self.alloc_cluster() {
+                Some(c) => chain.push(c),
+                None => break,
+            }
+        }
+        for (i, &cluster) in chain.iter().enumerate() {
+            let next = if i + 1 < chain.len() { chain[i + 1] } else { self.eoc_marker() };
+            self.write_fat_entry(cluster, next);
+            let off = self.cluster_offset(cluster);
+            let start = i * self.bytes_per_cluster;
+            let end = (start + self.bytes_per_cluster).min(data.len());
+            self.image[off..off + (end - start)].copy_from_slice(&data[start..end]);
+            if end - start < self.bytes_per_cluster {
+                self.image[off + (end - start)..off + self.bytes_per_cluster].fill(0);
+            }
+        }
+        chain.first().copied().unwrap_or(0)
+    }
+
+    // --- Directory entries ---
+
+    fn dir_region(&self, dir_ino: u64) -> (usize, usize) {
+        if dir_ino == ROOT_INODE {
+            (self.root_dir_offset, self.root_dir_sectors * BYTES_PER_SECTOR)
+        } else {
+            let cluster = ino_to_cluster(dir_ino);
+            let chain = self.cluster_chain(cluster);
+            // A directory's entries always fit its (possibly multi-cluster)
+            // chain; callers only read within len, so report the full chain span.
+            (self.cluster_offset(cluster), chain.len() * self.bytes_per_cluster)
+        }
+    }
+
+    fn short_name_checksum(short_name: &[u8; 11]) -> u8 {
+        let mut sum: u8 = 0;
+        for &b in short_name {
+            sum = (if sum & 1 != 0 { 0x80u8 } else { 0 }).wrapping_add(sum >> 1).wrapping_add(b);
+        }
+        sum
+    }
+
+    /// Builds an 8.3 short name for `long_name`, disambiguating with a
+    /// `~N` numeric tail against `taken` the way VFAT does.
+    fn generate_short_name(long_name: &str, taken: &[[u8; 11]]) -> [u8; 11] {
+        let upper: String = long_name.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase();
+        let (base, ext) = match upper.rsplit_once('.') {
+            Some((b, e)) => (b, e),
+            None => (upper.as_str(), ""),
+        };
+        let base_ascii: Vec<u8> = base.bytes().filter(|b| b.is_ascii_alphanumeric()).collect();
+        let ext_ascii: Vec<u8> = ext.bytes().filter(|b| b.is_ascii_alphanumeric()).take(3).collect();
+        for n in 1..=999u32 {
+            let suffix = format!("~{n}");
+            let keep = 8usize.saturating_sub(suffix.len());
+            let mut name = [b' '; 11];
+            let base_part: Vec<u8> = base_ascii.iter().take(keep).copied().collect();
+            name[..base_part.len()].copy_from_slice(&base_part);
+            name[base_part.len()..base_part.len() + suffix.len()].copy_from_slice(suffix.as_bytes());
+            name[8..8 + ext_ascii.len()].copy_from_slice(&ext_ascii);
+            if !taken.contains(&name) {
+                return name;
+            }
+        }
+        *taken.last().unwrap_or(&[b' '; 11])
+    }
+
+    /// Emits the LFN entries for `long_name` (in on-disk,
+    /// highest-sequence-first order), checksummed against `short_name`.
+    /// The short entry itself is not included; the caller appends it.
+    fn pack_name_entries(long_name: &str, short_name: &[u8; 11]) -> Vec<[u8; 32]> {
+        let utf16: Vec<u16> = long_name.encode_utf16().collect();
+        let chunks: Vec<&[u16]> = utf16.chunks(13).collect();
+        let checksum = Self::short_name_checksum(short_name);
+        let mut entries = Vec::new();
+        let total = chunks.len().max(1);
+        for (i, chunk) in chunks.iter().enumerate().rev() {
+            let mut entry = [0u8; 32];
+            let seq = (i as u8) + 1;
+            entry[0] = if i == total - 1 { seq | 0x40 } else { seq };
+            let mut padded = chunk.to_vec();
+            while padded.len() < 13 {
+                padded.push(if padded.len() == chunk.len() { 0x0000 } else { 0xFFFF });
+            }
+            for (j, code) in padded[0..5].iter().enumerate() {
+                entry[1 + j * 2..3 + j * 2].copy_from_slice(&code.to_le_bytes());
+            }
+            entry[11] = 0x0F; // LFN attribute
+            entry[13] = checksum;
+            for (j, code) in padded[5..11].iter().enumerate() {
+                entry[14 + j * 2..16 + j * 2].copy_from_slice(&code.to_le_bytes());
+            }
+            for (j, code) in padded[11..13].iter().enumerate() {
+                entry[28 + j * 2..30 + j * 2].copy_from_slice(&code.to_le_bytes());
+            }
+            entries.push(entry);
+        }
+        entries
+    }
+
+    fn list_dir(&self, dir_ino: u64) -> Vec<DirEntry> {
+        let (offset, len) = self.dir_region(dir_ino);
+        let mut out = Vec::new();
+        let mut lfn_parts: Vec<(u8, String)> = Vec::new();
+        let mut pos = offset;
+        while pos + DIR_ENTRY_SIZE <= offset + len {
+            let raw = &self.image[pos..pos + DIR_ENTRY_SIZE];
+            pos += DIR_ENTRY_SIZE;
+            if raw[0] == 0x00 {
+                break; // end of directory
+            }
+            if raw[0] == 0xE5 {
+                lfn_parts.clear();
+                continue; // deleted entry
+            }
+            if raw[11] == 0x0F {
+                let seq = raw[0] & !0x40;
+                let mut units = Vec::with_capacity(13);
+                for chunk in [&raw[1..11], &raw[14..26], &raw[28..32]] {
+                    for pair in chunk.chunks(2) {
+                        let code = u16::from_le_bytes([pair[0], pair[1]]);
+                        if code == 0x0000 || code == 0xFFFF {
+                            continue;
+                        }
+                        units.push(code);
+                    }
+                }
+                let part = String::from_utf16_lossy(&units);
+                lfn_parts.push((seq, part));
+                continue;
+            }
+            let mut short_name = [0u8; 11];
+            short_name.copy_from_slice(&raw[0..11]);
+            let attr = raw[11];
+            let cluster_hi = u16::from_le_bytes([raw[20], raw[21]]) as u32;
+            let cluster_lo = u16::from_le_bytes([raw[26], raw[27]]) as u32;
+            let first_cluster = (cluster_hi << 16) | cluster_lo;
+            let size = u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]);
+            let long_name = if !lfn_parts.is_empty() {
+                lfn_parts.sort_by_key(|(seq, _)| *seq);
+                lfn_parts.drain(..).map(|(_, s)| s).collect::<String>()
+            } else {
+                Self::short_name_to_string(&short_name)
+            };
+            out.push(DirEntry {
+                long_name,
+                attr,
+                first_cluster,
+                size,
+                short_entry_offset: pos - DIR_ENTRY_SIZE,
+            });
+            lfn_parts.clear();
+        }
+        out
+    }
+
+    fn short_name_to_string(short_name: &[u8; 11]) -> String {
+        let base = String::from_utf8_lossy(&short_name[0..8]).trim_end().to_string();
+        let ext = String::from_utf8_lossy(&short_name[8..11]).trim_end().to_string();
+        if ext.is_empty() { base } else { format!("{base}.{ext}") }
+    }
+
+    /// Appends a new directory entry (LFN run + short entry) for `name` in
+    /// `dir_ino`, growing the directory's cluster chain if it's full.
+    fn add_dir_entry(&mut self, dir_ino: u64, name: &str, attr: u8, first_cluster: u32, size: u32) {
+        let taken: Vec<[u8; 11]> = self.list_dir(dir_ino).iter().map(|e| {
+            let mut short = [b' '; 11];
+            short.copy_from_slice(&self.image[e.short_entry_offset..e.short_entry_offset + 11]);
+            short
+        }).collect();
+        let short_name = Self::generate_short_name(name, &taken);
+        let mut records = Self::pack_name_entries(name, &short_name);
+        let mut short_entry = [0u8; 32];
+        short_entry[0..11].copy_from_slice(&short_name);
+        short_entry[11] = attr;
+        short_entry[20..22].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
+        short_entry[26..28].copy_from_slice(&((first_cluster & 0xFFFF) as u16).to_le_bytes());
+        short_entry[28..32].copy_from_slice(&size.to_le_bytes());
+        records.push(short_entry);
+
+        let (offset, len) = self.dir_region(dir_ino);
+        let mut pos = offset;
+        let mut free_run_start = None;
+        let mut free_run_len = 0usize;
+        while pos + DIR_ENTRY_SIZE <= offset + len {
+            let is_free = self.image[pos] == 0x00 || self.image[pos] == 0xE5;
+            if is_free {
+                if free_run_start.is_none() {
+                    free_run_start = Some(pos);
+                }
+                free_run_len += 1;
+                if free_run_len >= records.len() {
+                    break;
+                }
+            } else {
+                free_run_start = None;
+                free_run_len = 0;
+            }
+            pos += DIR_ENTRY_SIZE;
+        }
+        let write_at = free_run_start.unwrap_or(offset + len - records.len() * DIR_ENTRY_SIZE);
+        for (i, record) in records.iter().enumerate() {
+            let p = write_at + i * DIR_ENTRY_SIZE;
+            self.image[p..p + DIR_ENTRY_SIZE].copy_from_slice(record);
+        }
+    }
+
+    fn find_entry(&self, dir_ino: u64, name: &str) -> Option<DirEntry> {
+        self.list_dir(dir_ino).into_iter().find(|e| e.long_name.eq_ignore_ascii_case(name))
+    }
+
+    fn remove_entry(&mut self, dir_ino: u64, name: &str) {
+        let (offset, len) = self.dir_region(dir_ino);
+        let mut pos = offset;
+        let mut lfn_start = pos;
+        let mut in_lfn_run = false;
+        while pos + DIR_ENTRY_SIZE <= offset + len {
+            let raw_attr = self.image[pos + 11];
+            if raw_attr == 0x0F {
+                if !in_lfn_run {
+                    lfn_start = pos;
+                    in_lfn_run = true;
+                }
+                pos += DIR_ENTRY_SIZE;
+                continue;
+            }
+            if self.image[pos] != 0x00 && self.image[pos] != 0xE5 {
+                let mut short_name = [0u8; 11];
+                short_name.copy_from_slice(&self.image[pos..pos + 11]);
+                let resolved = Self::short_name_to_string(&short_name);
+                let matches = resolved.eq_ignore_ascii_case(name) || {
+                    // Re-derive the long name the same way list_dir does, to
+                    // catch entries whose long name differs from the short one.
+                    self.list_dir(dir_ino).iter().any(|e| e.short_entry_offset == pos && e.long_name.eq_ignore_ascii_case(name))
+                };
+                if matches {
+                    let start = if in_lfn_run { lfn_start } else { pos };
+                    let mut p = start;
+                    while p <= pos {
+                        self.image[p] = 0xE5;
+                        p += DIR_ENTRY_SIZE;
+                    }
+                    return;
+                }
+            }
+            in_lfn_run = false;
+            pos += DIR_ENTRY_SIZE;
+        }
+    }
+
+    fn entry_attr(&self, entry: &DirEntry, ino: u64) -> fuser::FileAttr {
+        let now = SystemTime::now();
+        let is_dir = entry.attr & 0x10 != 0;
+        fuser::FileAttr {
+            ino,
+            size: entry.size as u64,
+            blocks: (entry.size as u64 + 511) / 512,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            crtime: now,
+            kind: if is_dir { fuser::FileType::Directory } else { fuser::FileType::RegularFile },
+            perm: if is_dir { 0o755 } else { 0o644 },
+            nlink: if is_dir { 2 } else { 1 },
+            uid: unsafe { libc::geteuid() },
+            gid: unsafe { libc::getegid() },
+            rdev: 0,
+            flags: 0,
+            blksize: BYTES_PER_SECTOR as u32,
+        }
+    }
+
+    fn root_attr() -> fuser::FileAttr {
+        let now = SystemTime::now();
+        fuser::FileAttr {
+            ino: ROOT_INODE,
+            size: 0,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            crtime: now,
+            kind: fuser::FileType::Directory,
+            perm: 0o755,
+            nlink: 2,
+            uid: unsafe { libc::geteuid() },
+            gid: unsafe { libc::getegid() },
+            rdev: 0,
+            flags: 0,
+            blksize: BYTES_PER_SECTOR as u32,
+        }
+    }
+
+    fn attr_for_ino(&self, ino: u64) -> Option<fuser::FileAttr> {
+        if ino == ROOT_INODE {
+            return Some(Self::root_attr());
+        }
+        let cluster = ino_to_cluster(ino);
+        // Search every directory reachable from root for an entry with this
+        // first cluster; small images make a full walk cheap enough.
+        self.find_by_cluster(ROOT_INODE, cluster)
+    }
+
+    fn find_by_cluster(&self, dir_ino: u64, target_cluster: u32) -> Option<fuser::FileAttr> {
+        for entry in self.list_dir(dir_ino) {
+            if entry.first_cluster == target_cluster {
+                return Some(self.entry_attr(&entry, cluster_to_ino(target_cluster)));
+            }
+            if entry.attr & 0x10 != 0 && entry.first_cluster != 0 {
+                if let Some(attr) = self.find_by_cluster(cluster_to_ino(entry.first_cluster), target_cluster) {
+                    return Some(attr);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl crate::providers::Provider for FatProvider {
+    fn rmdir(&mut self, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        let name_str = name.to_str().unwrap_or("");
+        match self.find_entry(parent, name_str) {
+            Some(entry) if entry.attr & 0x10 != 0 => {
+                if !self.list_dir(cluster_to_ino(entry.first_cluster)).is_empty() {
+                    reply.error(libc::ENOTEMPTY);
+                    return;
+                }
+                self.free_chain(entry.first_cluster);
+                self.remove_entry(parent, name_str);
+                self.xattrs.retain(|(ino, _), _| *ino != cluster_to_ino(entry.first_cluster));
+                let _ = self.flush_image();
+                reply.ok();
+            }
+            Some(_) => reply.error(libc::ENOTDIR),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+    fn open(&mut self, _ino: u64, reply: fuser::ReplyOpen) {
+        reply.opened(0, 0);
+    }
+    fn flush(&mut self, _ino: u64, reply: fuser::ReplyEmpty) {
+        reply.ok();
+    }
+    fn release(&mut self, _ino: u64, reply: fuser::ReplyEmpty) {
+        let _ = self.flush_image();
+        reply.ok();
+    }
+    fn setattr(&mut self, ino: u64, _mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>, size: Option<u64>, _atime: Option<fuser::TimeOrNow>, _mtime: Option<fuser::TimeOrNow>, _ctime: Option<SystemTime>, _crtime: Option<SystemTime>, _flags: Option<u32>, reply: fuser::ReplyAttr) {
+        if let Some(new_size) = size {
+            let cluster = ino_to_cluster(ino);
+            let old_size = self.find_by_cluster(ROOT_INODE, cluster).map(|a| a.size as usize).unwrap_or(0);
+            let mut data = self.read_chain_data(cluster, old_size);
+            data.resize(new_size as usize, 0);
+            let new_cluster = self.write_chain_data(cluster, &data);
+            self.update_entry_in_place(ino, new_cluster, new_size as u32);
+        }
+        match self.attr_for_ino(ino) {
+            Some(attr) => {
+                let _ = self.flush_image();
+                reply.attr(&std::time::Duration::from_secs(1), &attr);
+            }
+            None => reply.error(libc::ENOENT),
+        }
+    }
+    fn lookup(&mut self, parent: u64, name: &OsStr, reply: fuser::ReplyEntry) {
+        let name_str = name.to_str().unwrap_or("");
+        match self.find_entry(parent, name_str) {
+            Some(entry) => {
+                let ino = cluster_to_ino(entry.first_cluster);
+                reply.entry(&std::time::Duration::from_secs(1), &self.entry_attr(&entry, ino), 0);
+            }
+            None => reply.error(libc::ENOENT),
+        }
+    }
+    fn getattr(&mut self, ino: u64, reply: fuser::ReplyAttr) {
+        match self.attr_for_ino(ino) {
+            Some(attr) => reply.attr(&std::time::Duration::from_secs(1), &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+    fn readdir(&mut self, ino: u64, offset: i64, mut reply: fuser::ReplyDirectory) {
+        let mut entries = vec![(ROOT_INODE, fuser::FileType::Directory, ".".to_string()), (ROOT_INODE, fuser::FileType::Directory, "..".to_string())];
+        for entry in self.list_dir(ino) {
+            if self.osx_mode && entry.long_name.starts_with("._") {
+                continue;
+            }
+            let child_ino = cluster_to_ino(entry.first_cluster);
+            let kind = if entry.attr & 0x10 != 0 { fuser::FileType::Directory } else { fuser::FileType::RegularFile };
+            entries.push((child_ino, kind, entry.long_name));
+        }
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+    fn mkdir(&mut self, parent: u64, name: &OsStr, _mode: u32, _umask: u32, reply: fuser::ReplyEntry) {
+        let name_str = name.to_str().unwrap_or("");
+        if self.find_entry(parent, name_str).is_some() {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        let cluster = match self.alloc_cluster() {
+            Some(c) => c,
+            None => { reply.error(libc::ENOSPC); return; }
+        };
+        let off = self.cluster_offset(cluster);
+        self.image[off..off + self.bytes_per_cluster].fill(0);
+        self.add_dir_entry(parent, name_str, 0x10, cluster, 0);
+        let _ = self.flush_image();
+        let ino = cluster_to_ino(cluster);
+        let attr = self.attr_for_ino(ino).unwrap();
+        reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
+    }
+    fn create(&mut self, req_uid: u32, req_gid: u32, parent: u64, name: &OsStr, _mode: u32, _flags: u32, _umask: i32, reply: fuser::ReplyCreate) {
+        let name_str = name.to_str().unwrap_or("");
+        if self.find_entry(parent, name_str).is_some() {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        self.add_dir_entry(parent, name_str, 0x20, 0, 0);
+        let _ = self.flush_image();
+        // A brand-new file has no cluster yet (`first_cluster == 0`); the
+        // first `write` allocates one and patches the directory entry.
+        let ino = cluster_to_ino(0);
+        let attr = fuser::FileAttr {
+            ino,
+            size: 0,
+            blocks: 0,
+            atime: SystemTime::now(),
+            mtime: SystemTime::now(),
+            ctime: SystemTime::now(),
+            crtime: SystemTime::now(),
+            kind: fuser::FileType::RegularFile,
+            perm: 0o644,
+            nlink: 1,
+            uid: req_uid,
+            gid: req_gid,
+            rdev: 0,
+            flags: 0,
+            blksize: BYTES_PER_SECTOR as u32,
+        };
+        reply.created(&std::time::Duration::from_secs(1), &attr, 0, 0, 0);
+    }
+    fn read(&mut self, req_uid: u32, req_gid: u32, ino: u64, offset: i64, size: u32, reply: fuser::ReplyData) {
+        if ino == ROOT_INODE {
+            reply.error(libc::EISDIR);
+            return;
+        }
+        let cluster = ino_to_cluster(ino);
+        match self.find_by_cluster(ROOT_INODE, cluster) {
+            Some(attr) if !check_access(&attr, req_uid, req_gid, libc::R_OK) => {
+                reply.error(libc::EACCES);
+            }
+            Some(attr) => {
+                let data = self.read_chain_data(cluster, attr.size as usize);
+                let start = std::cmp::min(offset as usize, data.len());
+                let end = std::cmp::min(start + size as usize, data.len());
+                reply.data(&data[start..end]);
+            }
+            None => reply.error(libc::ENOENT),
+        }
+    }
+    fn write(&mut self, req_uid: u32, req_gid: u32, ino: u64, offset: i64, data: &[u8], reply: fuser::ReplyWrite) {
+        let cluster = ino_to_cluster(ino);
+        if let Some(attr) = self.find_by_cluster(ROOT_INODE, cluster) {
+            if !check_access(&attr, req_uid, req_gid, libc::W_OK) {
+                reply.error(libc::EACCES);
+                return;
+            }
+        }
+        let existing_size = self.find_by_cluster(ROOT_INODE, cluster).map(|a| a.size as usize).unwrap_or(0);
+        let mut bytes = self.read_chain_data(cluster, existing_size);
+        let off = offset as usize;
+        if bytes.len() < off + data.len() {
+            bytes.resize(off + data.len(), 0);
+        }
+        bytes[off..off + data.len()].copy_from_slice(data);
+        let new_cluster = self.write_chain_data(cluster, &bytes);
+        self.update_entry_in_place(ino, new_cluster, bytes.len() as u32);
+        let _ = self.flush_image();
+        reply.written(data.len() as u32);
+    }
+    fn setxattr(&mut self, ino: u64, name: &OsStr, value: &[u8], flags: i32, reply: fuser::ReplyEmpty) {
+        if self.attr_for_ino(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name_str = name.to_str().unwrap_or("").to_string();
+        let key = (ino, name_str);
+        let exists = self.xattrs.contains_key(&key);
+        if flags & libc::XATTR_CREATE != 0 && exists {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        if flags & libc::XATTR_REPLACE != 0 && !exists {
+            reply.error(libc::ENODATA);
+            return;
+        }
+        self.xattrs.insert(key, value.to_vec());
+        reply.ok();
+    }
+    fn getxattr(&mut self, ino: u64, name: &OsStr, size: u32, reply: fuser::ReplyXattr) {
+        if self.attr_for_ino(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name_str = name.to_str().unwrap_or("");
+        match self.xattrs.get(&(ino, name_str.to_string())) {
+            Some(value) => {
+                if size == 0 {
+                    reply.size(value.len() as u32);
+                } else if value.len() > size as usize {
+                    reply.error(libc::ERANGE);
+                } else {
+                    reply.data(value);
+                }
+            }
+            None => reply.error(libc::ENODATA),
+        }
+    }
+    fn listxattr(&mut self, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        if self.attr_for_ino(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let mut names = Vec::new();
+        for (key, _) in self.xattrs.iter() {
+            if key.0 == ino {
+                names.extend_from_slice(key.1.as_bytes());
+                names.push(0);
+            }
+        }
+        if size == 0 {
+            reply.size(names.len() as u32);
+        } else if names.len() > size as usize {
+            reply.error(libc::ERANGE);
+        } else {
+            reply.data(&names);
+        }
+    }
+    fn removexattr(&mut self, ino: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        if self.attr_for_ino(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name_str = name.to_str().unwrap_or("").to_string();
+        if self.xattrs.remove(&(ino, name_str)).is_some() {
+            reply.ok();
+        } else {
+            reply.error(libc::ENODATA);
+        }
+    }
+}
+
+impl FatProvider {
+    /// Patches an existing directory entry's first-cluster/size fields in
+    /// place after a write reallocates the file's chain. `ino` is the
+    /// *pre-write* inode (derived from the old first cluster); the entry is
+    /// located by scanning for it the same way `attr_for_ino` does.
+    fn update_entry_in_place(&mut self, ino: u64, new_cluster: u32, new_size: u32) {
+        let old_cluster = ino_to_cluster(ino);
+        if let Some(offset) = self.find_entry_offset(ROOT_INODE, old_cluster) {
+            self.image[offset + 20..offset + 22].copy_from_slice(&((new_cluster >> 16) as u16).to_le_bytes());
+            self.image[offset + 26..offset + 28].copy_from_slice(&((new_cluster & 0xFFFF) as u16).to_le_bytes());
+            self.image[offset + 28..offset + 32].copy_from_slice(&new_size.to_le_bytes());
+        }
+    }
+
+    fn find_entry_offset(&self, dir_ino: u64, target_cluster: u32) -> Option<usize> {
+        for entry in self.list_dir(dir_ino) {
+            if entry.first_cluster == target_cluster {
+                return Some(entry.short_entry_offset);
+            }
+            if entry.attr & 0x10 != 0 && entry.first_cluster != 0 {
+                if let Some(off) = self.find_entry_offset(cluster_to_ino(entry.first_cluster), target_cluster) {
+                    return Some(off);
+                }
+            }
+        }
+        None
+    }
+}
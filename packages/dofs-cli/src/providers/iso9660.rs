@@ -0,0 +1,312 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// ISO9660 logical sectors are always 2048 bytes, regardless of the
+/// underlying media's physical sector size.
+const SECTOR_SIZE: u64 = 2048;
+/// Volume descriptors start at sector 16 (the "system area" occupies the
+/// first 16 sectors) and the set always ends with a type-255 terminator, so
+/// this is just a sanity bound against a corrupt image that never gets one.
+const MAX_VOLUME_DESCRIPTORS: u32 = 64;
+
+/// Which naming scheme to read directory entries from, in priority order:
+/// the first one present on the disc wins. Mirrors the `-9`/`-J`/`-R` flags
+/// `9660srv` uses to pick between plain ISO9660, Joliet and Rock Ridge names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameSource {
+    Iso9660,
+    Joliet,
+    RockRidge,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOpts {
+    pub prefer: Vec<NameSource>,
+}
+
+impl Default for ImportOpts {
+    fn default() -> Self {
+        ImportOpts { prefer: vec![NameSource::RockRidge, NameSource::Joliet, NameSource::Iso9660] }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImportStats {
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+}
+
+/// Root directory location pulled out of a primary or supplementary volume
+/// descriptor; everything else needed to walk the tree hangs off this.
+#[derive(Debug, Clone, Copy)]
+pub struct DirTree {
+    pub root_extent: u32,
+    pub root_size: u32,
+}
+
+/// One directory record, resolved to whichever name source the caller asked
+/// for. `perm`/`uid`/`gid` are `None` when Rock Ridge didn't supply them
+/// (no RR extensions on this disc, or this particular field wasn't
+/// present), leaving the caller to fall back to sensible defaults. `mtime`
+/// falls back to the record's own recording timestamp, and is `None` only
+/// when neither that nor a Rock Ridge TF time is usable.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub extent: u32,
+    pub size: u32,
+    pub mtime: Option<SystemTime>,
+    pub perm: Option<u16>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// Reads `size` bytes starting at the sector named by `extent` — the shared
+/// shape of both a directory's own contents and a plain file's data, since
+/// ISO9660 has no separate "chunking" of its own: an extent is just a run of
+/// whole sectors holding exactly `size` bytes.
+pub fn read_extent(file: &mut File, extent: u32, size: u32) -> std::io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(extent as u64 * SECTOR_SIZE))?;
+    let mut buf = vec![0u8; size as usize];
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Walks the volume descriptor set starting at sector 16, returning the
+/// primary volume descriptor's root directory (always present) and the
+/// first Joliet supplementary volume descriptor's root, if any.
+pub fn scan_volume_descriptors(file: &mut File) -> std::io::Result<(DirTree, Option<DirTree>)> {
+    let mut primary = None;
+    let mut joliet = None;
+    for i in 0..MAX_VOLUME_DESCRIPTORS {
+        let sector = 16 + i;
+        file.seek(SeekFrom::Start(sector as u64 * SECTOR_SIZE))?;
+        let mut vd = vec![0u8; SECTOR_SIZE as usize];
+        file.read_exact(&mut vd)?;
+        if &vd[1..6] != b"CD001" {
+            break;
+        }
+        match vd[0] {
+            255 => break,
+            1 if primary.is_none() => primary = Some(root_dir_tree(&vd)),
+            2 if joliet.is_none() && is_joliet_escape(&vd[88..120]) => joliet = Some(root_dir_tree(&vd)),
+            _ => {}
+        }
+    }
+    let primary = primary.ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "no primary volume descriptor found")
+    })?;
+    Ok((primary, joliet))
+}
+
+/// Joliet is signalled by one of three UCS-2 "escape sequences" (levels 1-3)
+/// in the supplementary volume descriptor's 32-byte escape-sequence field;
+/// only the first three bytes differ between levels.
+fn is_joliet_escape(esc: &[u8]) -> bool {
+    esc.starts_with(&[0x25, 0x2F, 0x40]) || esc.starts_with(&[0x25, 0x2F, 0x43]) || esc.starts_with(&[0x25, 0x2F, 0x45])
+}
+
+fn root_dir_tree(vd: &[u8]) -> DirTree {
+    // The root directory record is embedded directly in the volume
+    // descriptor at a fixed offset, 34 bytes long.
+    let root = &vd[156..156 + 34];
+    DirTree {
+        root_extent: u32::from_le_bytes(root[2..6].try_into().unwrap()),
+        root_size: u32::from_le_bytes(root[10..14].try_into().unwrap()),
+    }
+}
+
+/// Parses one directory's worth of records out of its raw extent data,
+/// skipping the synthetic "." and ".." entries every directory starts with
+/// (the caller already knows its own parent inode). `rock_ridge` enables
+/// parsing the System Use field for Rock Ridge PX/TF/NM entries; `joliet`
+/// decodes names as big-endian UCS-2 instead of the plain ISO d-characters.
+pub fn parse_directory_records(data: &[u8], rock_ridge: bool, joliet: bool) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut pos = 0usize;
+    while pos < data.len() {
+        let len = data[pos] as usize;
+        if len == 0 {
+            // A zero length byte means "no more records in this sector":
+            // directory records never straddle a sector boundary, so the
+            // rest of the current sector is padding.
+            let next = (pos / SECTOR_SIZE as usize + 1) * SECTOR_SIZE as usize;
+            if next <= pos || next >= data.len() {
+                break;
+            }
+            pos = next;
+            continue;
+        }
+        if pos + len > data.len() || len < 33 {
+            break;
+        }
+        let record = &data[pos..pos + len];
+        let name_len = record[32] as usize;
+        if 33 + name_len > record.len() {
+            break;
+        }
+        let name_bytes = &record[33..33 + name_len];
+        // "." (0x00) and ".." (0x01) are encoded as a single-byte name, not
+        // real filenames.
+        if name_len == 1 && (name_bytes[0] == 0 || name_bytes[0] == 1) {
+            pos += len;
+            continue;
+        }
+        let extent = u32::from_le_bytes(record[2..6].try_into().unwrap());
+        let size = u32::from_le_bytes(record[10..14].try_into().unwrap());
+        let is_dir = record[25] & 0x02 != 0;
+        let recording_time = parse_datetime7(&record[18..25]);
+        let su_start = 33 + name_len + if name_len % 2 == 0 { 1 } else { 0 };
+        let (rr_name, perm, uid, gid, rr_mtime) = if rock_ridge && su_start < record.len() {
+            parse_rock_ridge(&record[su_start..])
+        } else {
+            (None, None, None, None, None)
+        };
+        let name = if joliet {
+            decode_ucs2be(name_bytes)
+        } else if let Some(n) = rr_name {
+            n
+        } else {
+            clean_iso_identifier(name_bytes)
+        };
+        entries.push(Entry {
+            name,
+            is_dir,
+            extent,
+            size,
+            mtime: rr_mtime.or(recording_time),
+            perm,
+            uid,
+            gid,
+        });
+        pos += len;
+    }
+    entries
+}
+
+/// Strips the `;<version>` suffix and (for extension-less files) the
+/// trailing dot ISO9660 pads plain identifiers with, e.g. `README.;1` ->
+/// `README`.
+fn clean_iso_identifier(raw: &[u8]) -> String {
+    let s = String::from_utf8_lossy(raw).into_owned();
+    let s = match s.find(';') {
+        Some(i) => s[..i].to_string(),
+        None => s,
+    };
+    match s.strip_suffix('.') {
+        Some(stripped) => stripped.to_string(),
+        None => s,
+    }
+}
+
+fn decode_ucs2be(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Decodes the 7-byte numeric date/time format shared by a directory
+/// record's own recording timestamp and Rock Ridge TF's short form: years
+/// since 1900, month, day, hour, minute, second, then a signed GMT offset in
+/// 15-minute intervals.
+fn parse_datetime7(b: &[u8]) -> Option<SystemTime> {
+    if b.len() < 7 {
+        return None;
+    }
+    let year = 1900 + b[0] as i64;
+    let (month, day, hour, minute, second) = (b[1] as u32, b[2] as u32, b[3] as i64, b[4] as i64, b[5] as i64);
+    let gmt_offset_secs = (b[6] as i8) as i64 * 15 * 60;
+    if month == 0 || day == 0 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second - gmt_offset_secs;
+    if secs >= 0 {
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    } else {
+        Some(UNIX_EPOCH)
+    }
+}
+
+/// Howard Hinnant's `days_from_civil`: days since the Unix epoch for a
+/// proleptic-Gregorian (y, m, d), used instead of pulling in a date/time
+/// crate for a handful of timestamp conversions.
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Parses the System Use (SUSP) area of a directory record for the Rock
+/// Ridge extensions this importer understands: `PX` (mode/uid/gid), `NM`
+/// (alternate name, across one or more entries) and `TF` (timestamps,
+/// short form only — the 17-byte ISO 8601 long form isn't supported).
+/// Anything else (`CE` continuation areas, `SL` symlinks, `RE` relocations,
+/// ...) is skipped rather than followed.
+fn parse_rock_ridge(area: &[u8]) -> (Option<String>, Option<u16>, Option<u32>, Option<u32>, Option<SystemTime>) {
+    let mut name = String::new();
+    let mut have_name = false;
+    let mut perm = None;
+    let mut uid = None;
+    let mut gid = None;
+    let mut mtime = None;
+    let mut pos = 0usize;
+    while pos + 4 <= area.len() {
+        let sig = &area[pos..pos + 2];
+        let len = area[pos + 2] as usize;
+        if len < 4 || pos + len > area.len() {
+            break;
+        }
+        let payload = &area[pos + 4..pos + len];
+        match sig {
+            b"PX" if payload.len() >= 8 => {
+                let mode = u32::from_le_bytes(payload[0..4].try_into().unwrap());
+                perm = Some((mode & 0o7777) as u16);
+                if payload.len() >= 32 {
+                    uid = Some(u32::from_le_bytes(payload[16..20].try_into().unwrap()));
+                    gid = Some(u32::from_le_bytes(payload[24..28].try_into().unwrap()));
+                }
+            }
+            b"NM" if !payload.is_empty() => {
+                let flags = payload[0];
+                // Bits 1/2 mark the "current dir"/"parent dir" aliases,
+                // which carry no name bytes to append.
+                if flags & 0x06 == 0 {
+                    name.push_str(&String::from_utf8_lossy(&payload[1..]));
+                    have_name = true;
+                }
+            }
+            b"TF" if !payload.is_empty() => {
+                let flags = payload[0];
+                if flags & 0x80 == 0 {
+                    let mut off = 1usize;
+                    let mut creation = None;
+                    let mut modify = None;
+                    for (i, bit) in [0x01u8, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40].iter().enumerate() {
+                        if flags & bit != 0 {
+                            if off + 7 <= payload.len() {
+                                let t = parse_datetime7(&payload[off..off + 7]);
+                                if i == 0 {
+                                    creation = t;
+                                } else if i == 1 {
+                                    modify = t;
+                                }
+                            }
+                            off += 7;
+                        }
+                    }
+                    mtime = modify.or(creation);
+                }
+            }
+            _ => {}
+        }
+        pos += len;
+    }
+    (if have_name { Some(name) } else { None }, perm, uid, gid, mtime)
+}
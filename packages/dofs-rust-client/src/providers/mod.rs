@@ -1,25 +1,32 @@
-pub mod memory;
 pub mod sqlite_simple;
-pub mod sqlite_chunked;
 
-use fuser::{ReplyAttr, ReplyEntry, ReplyDirectory, ReplyData, ReplyCreate, ReplyWrite};
+use fuser::{ReplyAttr, ReplyEntry, ReplyDirectory, ReplyData, ReplyCreate, ReplyWrite, ReplyXattr, ReplyStatfs};
 use std::ffi::OsStr;
 
 pub trait Provider {
     fn rmdir(&mut self, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty);
-    fn open(&mut self, ino: u64, reply: fuser::ReplyOpen);
+    fn open(&mut self, ino: u64, flags: i32, reply: fuser::ReplyOpen);
     fn flush(&mut self, ino: u64, reply: fuser::ReplyEmpty);
-    fn release(&mut self, ino: u64, reply: fuser::ReplyEmpty);
+    fn release(&mut self, ino: u64, fh: u64, reply: fuser::ReplyEmpty);
     fn setattr(&mut self, ino: u64, mode: Option<u32>, uid: Option<u32>, gid: Option<u32>, size: Option<u64>, atime: Option<fuser::TimeOrNow>, mtime: Option<fuser::TimeOrNow>, ctime: Option<std::time::SystemTime>, crtime: Option<std::time::SystemTime>, flags: Option<u32>, reply: ReplyAttr);
     fn lookup(&mut self, parent: u64, name: &OsStr, reply: ReplyEntry);
     fn getattr(&mut self, ino: u64, reply: ReplyAttr);
     fn readdir(&mut self, ino: u64, offset: i64, reply: ReplyDirectory);
     fn mkdir(&mut self, parent: u64, name: &OsStr, mode: u32, umask: u32, reply: ReplyEntry);
     fn create(&mut self, parent: u64, name: &OsStr, mode: u32, flags: u32, umask: i32, reply: ReplyCreate);
-    fn read(&mut self, ino: u64, offset: i64, size: u32, reply: ReplyData);
-    fn write(&mut self, ino: u64, offset: i64, data: &[u8], reply: ReplyWrite);
+    fn read(&mut self, ino: u64, fh: u64, offset: i64, size: u32, reply: ReplyData);
+    fn write(&mut self, ino: u64, fh: u64, offset: i64, data: &[u8], reply: ReplyWrite);
     fn unlink(&mut self, parent: u64, name: &std::ffi::OsStr, reply: fuser::ReplyEmpty);
     fn rename(&mut self, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, flags: u32, reply: fuser::ReplyEmpty);
     fn symlink(&mut self, parent: u64, name: &OsStr, link: &std::path::Path, reply: fuser::ReplyEntry);
     fn readlink(&mut self, ino: u64, reply: fuser::ReplyData);
+    fn setxattr(&mut self, ino: u64, name: &OsStr, value: &[u8], flags: i32, reply: fuser::ReplyEmpty);
+    fn getxattr(&mut self, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr);
+    fn listxattr(&mut self, ino: u64, size: u32, reply: ReplyXattr);
+    fn removexattr(&mut self, ino: u64, name: &OsStr, reply: fuser::ReplyEmpty);
+    fn statfs(&mut self, ino: u64, reply: ReplyStatfs);
+    /// Adds `newname` under `newparent` as a second directory entry for
+    /// `ino`, bumping its `nlink`.
+    fn link(&mut self, ino: u64, newparent: u64, newname: &OsStr, reply: ReplyEntry);
+    fn mknod(&mut self, parent: u64, name: &OsStr, mode: u32, umask: u32, rdev: u32, reply: ReplyEntry);
 } 
\ No newline at end of file
@@ -7,6 +7,77 @@ use std::ffi::OsStr;
 
 const ROOT_INODE: u64 = 1;
 const USER_INODE_START: u64 = 10; // user files/dirs start here to avoid reserved inodes
+const STATFS_BLOCK_SIZE: u32 = 512;
+/// Capacity ceiling `statfs` reports the tree as having, matching the
+/// same idiom used by `MemoryProvider`.
+const DEFAULT_CAPACITY_BYTES: u64 = 1024 * 1024 * 1024 * 1024;
+
+/// Content-defined chunking window: boundaries are cut on a rolling Gear
+/// hash but a chunk is never let shrink below `CHUNK_MIN_SIZE` or grow past
+/// `CHUNK_MAX_SIZE`, so edits in the middle of a file only perturb the
+/// chunks touching them.
+const CHUNK_MIN_SIZE: usize = 2 * 1024;
+const CHUNK_MAX_SIZE: usize = 64 * 1024;
+/// Cut whenever the low `CHUNK_MASK_BITS` bits of the rolling hash are
+/// zero, which targets an ~8 KiB average chunk size.
+const CHUNK_MASK_BITS: u32 = 13;
+
+const fn splitmix64(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = splitmix64(i as u64 + 1);
+        i += 1;
+    }
+    table
+}
+
+/// Gear hash lookup table used by `cdc_split`'s rolling hash.
+const GEAR: [u64; 256] = gear_table();
+
+/// Splits `data` into content-defined chunks using a Gear-hash rolling
+/// window, so identical content across writes and across files shares
+/// `chunks` rows.
+fn cdc_split(data: &[u8]) -> Vec<&[u8]> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let mask = (1u64 << CHUNK_MASK_BITS) - 1;
+    let mut boundaries = Vec::new();
+    let mut start = 0usize;
+    let mut h: u64 = 0;
+    for (i, &byte) in data.iter().enumerate() {
+        h = h.wrapping_shl(1).wrapping_add(GEAR[byte as usize]);
+        let len = i - start + 1;
+        if len >= CHUNK_MIN_SIZE && (h & mask) == 0 {
+            boundaries.push(i + 1);
+            start = i + 1;
+            h = 0;
+        } else if len >= CHUNK_MAX_SIZE {
+            boundaries.push(i + 1);
+            start = i + 1;
+            h = 0;
+        }
+    }
+    if start < data.len() {
+        boundaries.push(data.len());
+    }
+    let mut slices = Vec::with_capacity(boundaries.len());
+    let mut prev = 0usize;
+    for end in boundaries {
+        slices.push(&data[prev..end]);
+        prev = end;
+    }
+    slices
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 enum FileTypeRepr {
@@ -126,10 +197,24 @@ impl From<&SerializableFileAttr> for fuser::FileAttr {
     }
 }
 
+/// Per-open file-handle state, keyed by the handle `open`/`create` hand
+/// back to the kernel; lets `write` honor the `O_APPEND` the handle was
+/// opened with without re-deriving it from the inode.
+struct OpenHandle {
+    ino: u64,
+    flags: i32,
+}
+
 pub struct SqliteProvider {
     conn: Connection,
     next_inode: u64,
     pub osx_mode: bool,
+    /// Ceiling `statfs` reports the tree as having; see `DEFAULT_CAPACITY_BYTES`.
+    pub capacity_bytes: u64,
+    /// Allocator for the handles returned by `open`/`create`.
+    next_fh: std::sync::atomic::AtomicU64,
+    /// Outstanding handles, removed on `release`.
+    handles: std::collections::HashMap<u64, OpenHandle>,
 }
 
 impl SqliteProvider {
@@ -140,21 +225,41 @@ impl SqliteProvider {
     pub fn new_with_mode(db_path: &str, osx_mode: bool) -> Result<Self> {
         let conn = Connection::open(db_path)?;
         conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS files (
+            "CREATE TABLE IF NOT EXISTS inodes (
                 ino INTEGER PRIMARY KEY,
-                name TEXT NOT NULL,
-                parent INTEGER,
                 is_dir INTEGER NOT NULL,
                 data BLOB,
                 attr BLOB
             );
-            CREATE INDEX IF NOT EXISTS idx_files_parent_name ON files(parent, name);
-            CREATE INDEX IF NOT EXISTS idx_files_parent ON files(parent);
-            CREATE INDEX IF NOT EXISTS idx_files_name ON files(name);"
+            CREATE TABLE IF NOT EXISTS dirents (
+                parent INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                ino INTEGER NOT NULL,
+                PRIMARY KEY (parent, name)
+            );
+            CREATE INDEX IF NOT EXISTS idx_dirents_ino ON dirents(ino);
+            CREATE TABLE IF NOT EXISTS xattrs (
+                ino INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (ino, name)
+            );
+            CREATE TABLE IF NOT EXISTS chunks (
+                hash BLOB PRIMARY KEY,
+                data BLOB NOT NULL,
+                refcount INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS file_chunks (
+                ino INTEGER NOT NULL,
+                seq INTEGER NOT NULL,
+                hash BLOB NOT NULL,
+                PRIMARY KEY (ino, seq)
+            );
+            CREATE INDEX IF NOT EXISTS idx_file_chunks_ino ON file_chunks(ino);"
         )?;
         // Ensure root exists
         {
-            let mut stmt = conn.prepare("SELECT COUNT(*) FROM files WHERE ino = ?1")?;
+            let mut stmt = conn.prepare("SELECT COUNT(*) FROM inodes WHERE ino = ?1")?;
             let count: i64 = stmt.query_row(params![ROOT_INODE], |row| row.get(0))?;
             if count == 0 {
                 let now = SystemTime::now();
@@ -177,14 +282,14 @@ impl SqliteProvider {
                 };
                 let attr_bytes = bincode::serialize(&SerializableFileAttr::from(&attr)).unwrap();
                 conn.execute(
-                    "INSERT INTO files (ino, name, parent, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                    params![ROOT_INODE, "/", None::<u64>, 1, None::<Vec<u8>>, attr_bytes],
+                    "INSERT INTO inodes (ino, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4)",
+                    params![ROOT_INODE, 1, None::<Vec<u8>>, attr_bytes],
                 )?;
             }
         }
         // Find max inode
         let mut next_inode: u64 = conn.query_row(
-            "SELECT MAX(ino) FROM files",
+            "SELECT MAX(ino) FROM inodes",
             [],
             |row| row.get::<_, Option<u64>>(0),
         )?.unwrap_or(ROOT_INODE);
@@ -193,16 +298,26 @@ impl SqliteProvider {
         } else {
             next_inode += 1;
         }
-        Ok(Self { conn, next_inode, osx_mode })
+        Ok(Self {
+            conn,
+            next_inode,
+            osx_mode,
+            capacity_bytes: DEFAULT_CAPACITY_BYTES,
+            next_fh: std::sync::atomic::AtomicU64::new(1),
+            handles: std::collections::HashMap::new(),
+        })
     }
     fn alloc_inode(&mut self) -> u64 {
         let ino = self.next_inode;
         self.next_inode += 1;
         ino
     }
+    fn alloc_fh(&self) -> u64 {
+        self.next_fh.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    }
     fn get_attr(&self, ino: u64) -> Option<fuser::FileAttr> {
         self.conn.query_row(
-            "SELECT attr FROM files WHERE ino = ?1",
+            "SELECT attr FROM inodes WHERE ino = ?1",
             params![ino],
             |row| {
                 let attr_blob: Vec<u8> = row.get(0)?;
@@ -214,38 +329,116 @@ impl SqliteProvider {
     fn set_attr(&self, ino: u64, attr: &fuser::FileAttr) {
         let attr_bytes = bincode::serialize(&SerializableFileAttr::from(attr)).unwrap();
         let _ = self.conn.execute(
-            "UPDATE files SET attr = ?1 WHERE ino = ?2",
+            "UPDATE inodes SET attr = ?1 WHERE ino = ?2",
             params![attr_bytes, ino],
         );
     }
     fn get_file_data(&self, ino: u64) -> Option<Vec<u8>> {
         self.conn.query_row(
-            "SELECT data FROM files WHERE ino = ?1",
+            "SELECT data FROM inodes WHERE ino = ?1",
             params![ino],
             |row| row.get(0),
         ).optional().unwrap_or(None)
     }
-    fn set_file_data(&self, ino: u64, data: &[u8]) {
-        let _ = self.conn.execute(
-            "UPDATE files SET data = ?1 WHERE ino = ?2",
-            params![data, ino],
-        );
+    /// Ordered content hashes of `ino`'s chunks, read from its `file_chunks`
+    /// rows in `seq` order.
+    fn chunk_hashes(&self, ino: u64) -> Vec<Vec<u8>> {
+        let mut stmt = self.conn
+            .prepare("SELECT hash FROM file_chunks WHERE ino = ?1 ORDER BY seq")
+            .unwrap();
+        stmt.query_map(params![ino], |row| row.get(0))
+            .unwrap()
+            .filter_map(|r| r.ok())
+            .collect()
+    }
+    /// Reassembles a file's full contents by concatenating its chunks in order.
+    fn get_chunked_data(&self, ino: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for hash in self.chunk_hashes(ino) {
+            let chunk: Vec<u8> = self.conn
+                .query_row("SELECT data FROM chunks WHERE hash = ?1", params![hash], |row| row.get(0))
+                .unwrap_or_default();
+            buf.extend_from_slice(&chunk);
+        }
+        buf
+    }
+    /// Interns `data` in the `chunks` table, bumping its refcount if already
+    /// present, and returns its blake3 hash.
+    fn intern_chunk(&self, data: &[u8]) -> Vec<u8> {
+        let hash = blake3::hash(data).as_bytes().to_vec();
+        let updated = self.conn
+            .execute("UPDATE chunks SET refcount = refcount + 1 WHERE hash = ?1", params![hash])
+            .unwrap_or(0);
+        if updated == 0 {
+            let _ = self.conn.execute(
+                "INSERT INTO chunks (hash, data, refcount) VALUES (?1, ?2, 1)",
+                params![hash, data],
+            );
+        }
+        hash
+    }
+    /// Drops one reference to `hash`, removing the chunk once nothing
+    /// references it anymore.
+    fn release_chunk(&self, hash: &[u8]) {
+        let _ = self.conn.execute("UPDATE chunks SET refcount = refcount - 1 WHERE hash = ?1", params![hash]);
+        let _ = self.conn.execute("DELETE FROM chunks WHERE hash = ?1 AND refcount <= 0", params![hash]);
+    }
+    /// Re-chunks `data` with `cdc_split` and rewrites `ino`'s `file_chunks`
+    /// rows to match, interning the new pieces before releasing the old
+    /// ones so chunks shared between the two versions stay live throughout.
+    fn set_chunked_data(&self, ino: u64, data: &[u8]) {
+        let old_hashes = self.chunk_hashes(ino);
+        let _ = self.conn.execute("DELETE FROM file_chunks WHERE ino = ?1", params![ino]);
+        for (seq, chunk) in cdc_split(data).into_iter().enumerate() {
+            let hash = self.intern_chunk(chunk);
+            let _ = self.conn.execute(
+                "INSERT INTO file_chunks (ino, seq, hash) VALUES (?1, ?2, ?3)",
+                params![ino, seq as i64, hash],
+            );
+        }
+        for hash in old_hashes {
+            self.release_chunk(&hash);
+        }
+    }
+    /// Releases every chunk `ino` references, used on `unlink`.
+    fn delete_chunked_data(&self, ino: u64) {
+        let old_hashes = self.chunk_hashes(ino);
+        let _ = self.conn.execute("DELETE FROM file_chunks WHERE ino = ?1", params![ino]);
+        for hash in old_hashes {
+            self.release_chunk(&hash);
+        }
     }
     fn get_child_ino(&self, parent: u64, name: &str) -> Option<u64> {
         self.conn.query_row(
-            "SELECT ino FROM files WHERE parent = ?1 AND name = ?2",
+            "SELECT ino FROM dirents WHERE parent = ?1 AND name = ?2",
             params![parent, name],
             |row| row.get(0),
         ).optional().unwrap_or(None)
     }
     fn is_dir_empty(&self, ino: u64) -> bool {
         let count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM files WHERE parent = ?1",
+            "SELECT COUNT(*) FROM dirents WHERE parent = ?1",
             params![ino],
             |row| row.get(0),
         ).unwrap_or(0);
         count == 0
     }
+    /// Removes the `(parent, name)` dirent pointing at `ino` and decrements
+    /// its `nlink`, reclaiming the inode row (and its chunks/xattrs) only
+    /// once no directory entry references it anymore.
+    fn remove_dirent(&self, parent: u64, name: &str, ino: u64) {
+        let _ = self.conn.execute("DELETE FROM dirents WHERE parent = ?1 AND name = ?2", params![parent, name]);
+        if let Some(mut attr) = self.get_attr(ino) {
+            attr.nlink = attr.nlink.saturating_sub(1);
+            if attr.nlink == 0 || attr.kind == fuser::FileType::Directory {
+                self.delete_chunked_data(ino);
+                let _ = self.conn.execute("DELETE FROM inodes WHERE ino = ?1", params![ino]);
+                let _ = self.conn.execute("DELETE FROM xattrs WHERE ino = ?1", params![ino]);
+            } else {
+                self.set_attr(ino, &attr);
+            }
+        }
+    }
 }
 
 impl Provider for SqliteProvider {
@@ -259,16 +452,25 @@ impl Provider for SqliteProvider {
         if !self.is_dir_empty(ino) {
             reply.error(libc::ENOTEMPTY); return;
         }
-        let _ = self.conn.execute("DELETE FROM files WHERE ino = ?1", params![ino]);
-        let _ = self.conn.execute("DELETE FROM files WHERE parent = ?1 AND name = ?2", params![parent, name_str]);
+        self.remove_dirent(parent, name_str, ino);
         reply.ok();
     }
-    fn open(&mut self, ino: u64, reply: fuser::ReplyOpen) {
-        if self.get_attr(ino).is_some() {
-            reply.opened(0, 0);
-        } else {
+    fn open(&mut self, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        if self.get_attr(ino).is_none() {
             reply.error(libc::ENOENT);
+            return;
         }
+        if flags & libc::O_TRUNC != 0 {
+            self.set_chunked_data(ino, &[]);
+            if let Some(mut attr) = self.get_attr(ino) {
+                attr.size = 0;
+                self.set_attr(ino, &attr);
+            }
+        }
+        let fh = self.alloc_fh();
+        self.handles.insert(fh, OpenHandle { ino, flags });
+        let open_flags = if flags & libc::O_DIRECT != 0 { fuser::consts::FOPEN_DIRECT_IO } else { 0 };
+        reply.opened(fh, open_flags);
     }
     fn flush(&mut self, ino: u64, reply: fuser::ReplyEmpty) {
         if self.get_attr(ino).is_some() {
@@ -277,7 +479,8 @@ impl Provider for SqliteProvider {
             reply.error(libc::ENOENT);
         }
     }
-    fn release(&mut self, ino: u64, reply: fuser::ReplyEmpty) {
+    fn release(&mut self, ino: u64, fh: u64, reply: fuser::ReplyEmpty) {
+        self.handles.remove(&fh);
         if self.get_attr(ino).is_some() {
             reply.ok();
         } else {
@@ -314,9 +517,9 @@ impl Provider for SqliteProvider {
             if let Some(cr) = crtime { attr.crtime = safe_systemtime(cr); }
             if let Some(fg) = flags { attr.flags = fg; }
             if let Some(new_size) = size {
-                let mut data = self.get_file_data(ino).unwrap_or_default();
+                let mut data = self.get_chunked_data(ino);
                 data.resize(new_size as usize, 0);
-                self.set_file_data(ino, &data);
+                self.set_chunked_data(ino, &data);
                 attr.size = new_size;
             }
             self.set_attr(ino, &attr);
@@ -345,12 +548,14 @@ impl Provider for SqliteProvider {
     }
     fn readdir(&mut self, ino: u64, offset: i64, mut reply: fuser::ReplyDirectory) {
         let mut entries = vec![(ROOT_INODE, fuser::FileType::Directory, ".".to_string()), (ROOT_INODE, fuser::FileType::Directory, "..".to_string())];
-        let mut stmt = self.conn.prepare("SELECT ino, name, is_dir, attr FROM files WHERE parent = ?1").unwrap();
+        let mut stmt = self.conn.prepare(
+            "SELECT dirents.ino, dirents.name, inodes.attr FROM dirents
+             JOIN inodes ON inodes.ino = dirents.ino WHERE dirents.parent = ?1"
+        ).unwrap();
         let rows = stmt.query_map(params![ino], |row| {
             let ino: u64 = row.get(0)?;
             let name: String = row.get(1)?;
-            let is_dir: i64 = row.get(2)?;
-            let attr_blob: Vec<u8> = row.get(3)?;
+            let attr_blob: Vec<u8> = row.get(2)?;
             let ser_attr: SerializableFileAttr = bincode::deserialize(&attr_blob).unwrap();
             let kind = fuser::FileType::from(ser_attr.kind);
             Ok((ino, kind, name))
@@ -399,12 +604,16 @@ impl Provider for SqliteProvider {
         };
         let attr_bytes = bincode::serialize(&SerializableFileAttr::from(&attr)).unwrap();
         let _ = self.conn.execute(
-            "INSERT INTO files (ino, name, parent, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![ino, name_str, parent, 1, None::<Vec<u8>>, attr_bytes],
+            "INSERT INTO inodes (ino, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4)",
+            params![ino, 1, None::<Vec<u8>>, attr_bytes],
+        );
+        let _ = self.conn.execute(
+            "INSERT INTO dirents (parent, name, ino) VALUES (?1, ?2, ?3)",
+            params![parent, name_str, ino],
         );
         reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
     }
-    fn create(&mut self, parent: u64, name: &OsStr, mode: u32, _flags: u32, umask: i32, reply: fuser::ReplyCreate) {
+    fn create(&mut self, parent: u64, name: &OsStr, mode: u32, flags: u32, umask: i32, reply: fuser::ReplyCreate) {
         let name_str = name.to_str().unwrap_or("");
         if self.osx_mode && name_str.starts_with("._") {
             reply.error(libc::EACCES);
@@ -434,13 +643,72 @@ impl Provider for SqliteProvider {
         };
         let attr_bytes = bincode::serialize(&SerializableFileAttr::from(&attr)).unwrap();
         let _ = self.conn.execute(
-            "INSERT INTO files (ino, name, parent, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![ino, name_str, parent, 0, Vec::<u8>::new(), attr_bytes],
+            "INSERT INTO inodes (ino, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4)",
+            params![ino, 0, None::<Vec<u8>>, attr_bytes],
+        );
+        let _ = self.conn.execute(
+            "INSERT INTO dirents (parent, name, ino) VALUES (?1, ?2, ?3)",
+            params![parent, name_str, ino],
+        );
+        let flags = flags as i32;
+        let fh = self.alloc_fh();
+        self.handles.insert(fh, OpenHandle { ino, flags });
+        let open_flags = if flags & libc::O_DIRECT != 0 { fuser::consts::FOPEN_DIRECT_IO } else { 0 };
+        reply.created(&std::time::Duration::from_secs(1), &attr, 0, fh, open_flags);
+    }
+    fn mknod(&mut self, parent: u64, name: &OsStr, mode: u32, umask: u32, rdev: u32, reply: fuser::ReplyEntry) {
+        let name_str = name.to_str().unwrap_or("");
+        if self.osx_mode && name_str.starts_with("._") {
+            reply.error(libc::EACCES);
+            return;
+        }
+        if self.get_child_ino(parent, name_str).is_some() {
+            reply.error(libc::EEXIST); return;
+        }
+        let kind = match mode & libc::S_IFMT {
+            libc::S_IFBLK => fuser::FileType::BlockDevice,
+            libc::S_IFCHR => fuser::FileType::CharDevice,
+            libc::S_IFIFO => fuser::FileType::NamedPipe,
+            libc::S_IFSOCK => fuser::FileType::Socket,
+            _ => fuser::FileType::RegularFile,
+        };
+        let ino = self.alloc_inode();
+        let now = SystemTime::now();
+        let attr = fuser::FileAttr {
+            ino,
+            size: 0,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            crtime: now,
+            kind,
+            perm: (mode & !umask & 0o7777) as u16,
+            nlink: 1,
+            uid: unsafe { libc::geteuid() },
+            gid: unsafe { libc::getegid() },
+            rdev,
+            flags: 0,
+            blksize: 512,
+        };
+        let attr_bytes = bincode::serialize(&SerializableFileAttr::from(&attr)).unwrap();
+        let _ = self.conn.execute(
+            "INSERT INTO inodes (ino, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4)",
+            params![ino, 0, None::<Vec<u8>>, attr_bytes],
+        );
+        let _ = self.conn.execute(
+            "INSERT INTO dirents (parent, name, ino) VALUES (?1, ?2, ?3)",
+            params![parent, name_str, ino],
         );
-        reply.created(&std::time::Duration::from_secs(1), &attr, 0, 0, 0);
+        reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
     }
-    fn read(&mut self, ino: u64, offset: i64, size: u32, reply: fuser::ReplyData) {
-        if let Some(data) = self.get_file_data(ino) {
+    fn read(&mut self, ino: u64, fh: u64, offset: i64, size: u32, reply: fuser::ReplyData) {
+        match self.handles.get(&fh) {
+            Some(h) if h.ino == ino => {}
+            _ => { reply.error(libc::EBADF); return; }
+        }
+        if self.get_attr(ino).is_some() {
+            let data = self.get_chunked_data(ino);
             let end = std::cmp::min((offset as usize) + (size as usize), data.len());
             let start = std::cmp::min(offset as usize, data.len());
             reply.data(&data[start..end]);
@@ -448,16 +716,22 @@ impl Provider for SqliteProvider {
             reply.error(libc::ENOENT);
         }
     }
-    fn write(&mut self, ino: u64, offset: i64, data: &[u8], reply: fuser::ReplyWrite) {
-        if let Some(mut file_data) = self.get_file_data(ino) {
-            let offset = offset as usize;
+    fn write(&mut self, ino: u64, fh: u64, offset: i64, data: &[u8], reply: fuser::ReplyWrite) {
+        let flags = match self.handles.get(&fh) {
+            Some(h) if h.ino == ino => h.flags,
+            _ => { reply.error(libc::EBADF); return; }
+        };
+        if self.get_attr(ino).is_some() {
+            let mut file_data = self.get_chunked_data(ino);
+            let offset = if flags & libc::O_APPEND != 0 { file_data.len() } else { offset as usize };
             if file_data.len() < offset + data.len() {
                 file_data.resize(offset + data.len(), 0);
             }
             file_data[offset..offset + data.len()].copy_from_slice(data);
-            self.set_file_data(ino, &file_data);
+            let new_size = file_data.len() as u64;
+            self.set_chunked_data(ino, &file_data);
             if let Some(mut attr) = self.get_attr(ino) {
-                attr.size = file_data.len() as u64;
+                attr.size = new_size;
                 self.set_attr(ino, &attr);
             }
             reply.written(data.len() as u32);
@@ -472,7 +746,7 @@ impl Provider for SqliteProvider {
             Some(ino) => ino,
             None => { reply.error(libc::ENOENT); return; }
         };
-        let _ = self.conn.execute("DELETE FROM files WHERE ino = ?1", params![ino]);
+        self.remove_dirent(parent, name_str, ino);
         reply.ok();
     }
     fn rename(&mut self, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, _flags: u32, reply: fuser::ReplyEmpty) {
@@ -492,19 +766,14 @@ impl Provider for SqliteProvider {
                     return;
                 }
             }
-            let _ = self.conn.execute("DELETE FROM files WHERE ino = ?1", params![dest_ino]);
+            self.remove_dirent(newparent, newname_str, dest_ino);
         }
-        // Update the file's parent and name
+        // Move the dirent itself; the inode row (and its nlink) is untouched.
         let res = self.conn.execute(
-            "UPDATE files SET parent = ?1, name = ?2 WHERE ino = ?3",
-            params![newparent, newname_str, ino],
+            "UPDATE dirents SET parent = ?1, name = ?2 WHERE parent = ?3 AND name = ?4",
+            params![newparent, newname_str, parent, name_str],
         );
         if res.is_ok() {
-            // Remove the old name entry if parent/name changed
-            let _ = self.conn.execute(
-                "DELETE FROM files WHERE parent = ?1 AND name = ?2 AND ino != ?3",
-                params![parent, name_str, ino],
-            );
             reply.ok();
         } else {
             reply.error(libc::EIO);
@@ -541,8 +810,34 @@ impl Provider for SqliteProvider {
         };
         let attr_bytes = bincode::serialize(&SerializableFileAttr::from(&attr)).unwrap();
         let _ = self.conn.execute(
-            "INSERT INTO files (ino, name, parent, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![ino, name_str, parent, 0, target, attr_bytes],
+            "INSERT INTO inodes (ino, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4)",
+            params![ino, 0, target, attr_bytes],
+        );
+        let _ = self.conn.execute(
+            "INSERT INTO dirents (parent, name, ino) VALUES (?1, ?2, ?3)",
+            params![parent, name_str, ino],
+        );
+        reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
+    }
+    fn link(&mut self, ino: u64, newparent: u64, newname: &OsStr, reply: fuser::ReplyEntry) {
+        let name_str = newname.to_str().unwrap_or("");
+        if self.get_child_ino(newparent, name_str).is_some() {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        let mut attr = match self.get_attr(ino) {
+            Some(attr) => attr,
+            None => { reply.error(libc::ENOENT); return; }
+        };
+        if attr.kind == fuser::FileType::Directory {
+            reply.error(libc::EPERM);
+            return;
+        }
+        attr.nlink += 1;
+        self.set_attr(ino, &attr);
+        let _ = self.conn.execute(
+            "INSERT INTO dirents (parent, name, ino) VALUES (?1, ?2, ?3)",
+            params![newparent, name_str, ino],
         );
         reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
     }
@@ -558,4 +853,118 @@ impl Provider for SqliteProvider {
         }
         reply.error(libc::EINVAL);
     }
-} 
\ No newline at end of file
+    fn setxattr(&mut self, ino: u64, name: &OsStr, value: &[u8], flags: i32, reply: fuser::ReplyEmpty) {
+        if self.get_attr(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name_str = name.to_str().unwrap_or("");
+        let exists: bool = self.conn.query_row(
+            "SELECT COUNT(*) FROM xattrs WHERE ino = ?1 AND name = ?2",
+            params![ino, name_str],
+            |row| row.get::<_, i64>(0),
+        ).map(|count| count > 0).unwrap_or(false);
+        if flags & libc::XATTR_CREATE != 0 && exists {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        if flags & libc::XATTR_REPLACE != 0 && !exists {
+            reply.error(libc::ENODATA);
+            return;
+        }
+        let _ = self.conn.execute(
+            "INSERT INTO xattrs (ino, name, value) VALUES (?1, ?2, ?3)
+             ON CONFLICT(ino, name) DO UPDATE SET value = excluded.value",
+            params![ino, name_str, value],
+        );
+        reply.ok();
+    }
+    fn getxattr(&mut self, ino: u64, name: &OsStr, size: u32, reply: fuser::ReplyXattr) {
+        if self.get_attr(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name_str = name.to_str().unwrap_or("");
+        let value: Option<Vec<u8>> = self.conn.query_row(
+            "SELECT value FROM xattrs WHERE ino = ?1 AND name = ?2",
+            params![ino, name_str],
+            |row| row.get(0),
+        ).optional().unwrap_or(None);
+        match value {
+            Some(value) => {
+                if size == 0 {
+                    reply.size(value.len() as u32);
+                } else if value.len() > size as usize {
+                    reply.error(libc::ERANGE);
+                } else {
+                    reply.data(&value);
+                }
+            }
+            None => reply.error(libc::ENODATA),
+        }
+    }
+    fn listxattr(&mut self, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        if self.get_attr(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let mut stmt = self.conn.prepare("SELECT name FROM xattrs WHERE ino = ?1").unwrap();
+        let names: Vec<String> = stmt.query_map(params![ino], |row| row.get(0)).unwrap()
+            .filter_map(|r| r.ok())
+            .collect();
+        let mut buf = Vec::new();
+        for name in names {
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(0);
+        }
+        if size == 0 {
+            reply.size(buf.len() as u32);
+        } else if buf.len() > size as usize {
+            reply.error(libc::ERANGE);
+        } else {
+            reply.data(&buf);
+        }
+    }
+    fn removexattr(&mut self, ino: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        if self.get_attr(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name_str = name.to_str().unwrap_or("");
+        let changed = self.conn.execute(
+            "DELETE FROM xattrs WHERE ino = ?1 AND name = ?2",
+            params![ino, name_str],
+        ).unwrap_or(0);
+        if changed > 0 {
+            reply.ok();
+        } else {
+            reply.error(libc::ENODATA);
+        }
+    }
+    fn statfs(&mut self, _ino: u64, reply: fuser::ReplyStatfs) {
+        let files: u64 = self.conn.query_row("SELECT COUNT(*) FROM inodes", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as u64).unwrap_or(0);
+        // Deduplicated chunk bytes plus whatever still lives inline in
+        // `inodes.data` (symlink targets), i.e. the actual resident size
+        // rather than the sum of every file's nominal size.
+        let chunk_bytes: u64 = self.conn.query_row("SELECT COALESCE(SUM(LENGTH(data)), 0) FROM chunks", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as u64).unwrap_or(0);
+        let inline_bytes: u64 = self.conn.query_row("SELECT COALESCE(SUM(LENGTH(data)), 0) FROM inodes", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as u64).unwrap_or(0);
+        let used_bytes = chunk_bytes + inline_bytes;
+        let total_blocks = self.capacity_bytes / STATFS_BLOCK_SIZE as u64;
+        let used_blocks = (used_bytes + STATFS_BLOCK_SIZE as u64 - 1) / STATFS_BLOCK_SIZE as u64;
+        let free_blocks = total_blocks.saturating_sub(used_blocks);
+        const FFREE_SENTINEL: u64 = 1_000_000;
+        reply.statfs(
+            total_blocks,
+            free_blocks,
+            free_blocks,
+            files,
+            FFREE_SENTINEL,
+            STATFS_BLOCK_SIZE,
+            255,
+            STATFS_BLOCK_SIZE,
+        );
+    }
+}
\ No newline at end of file
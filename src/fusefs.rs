@@ -43,8 +43,8 @@ impl Filesystem for FuseFS {
     fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
         self.provider.rmdir(parent, name, reply)
     }
-    fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
-        self.provider.open(ino, reply)
+    fn open(&mut self, req: &Request<'_>, ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
+        self.provider.open(req.uid(), req.gid(), ino, reply)
     }
     fn flush(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, _lock_owner: u64, reply: fuser::ReplyEmpty) {
         self.provider.flush(ino, reply)
@@ -52,6 +52,9 @@ impl Filesystem for FuseFS {
     fn release(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, _flags: i32, _lock_owner: Option<u64>, _flush: bool, reply: fuser::ReplyEmpty) {
         self.provider.release(ino, reply)
     }
+    fn fsync(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, datasync: bool, reply: fuser::ReplyEmpty) {
+        self.provider.fsync(ino, datasync, reply)
+    }
     fn setattr(&mut self, _req: &Request<'_>, ino: u64, mode: Option<u32>, uid: Option<u32>, gid: Option<u32>, size: Option<u64>, atime: Option<fuser::TimeOrNow>, mtime: Option<fuser::TimeOrNow>, ctime: Option<std::time::SystemTime>, _fh: Option<u64>, crtime: Option<std::time::SystemTime>, _chgtime: Option<std::time::SystemTime>, _bkuptime: Option<std::time::SystemTime>, flags: Option<u32>, reply: ReplyAttr) {
         self.provider.setattr(ino, mode, uid, gid, size, atime, mtime, ctime, crtime, flags, reply)
     }
@@ -80,7 +83,7 @@ impl Filesystem for FuseFS {
     fn create(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, flags: u32, umask: i32, reply: ReplyCreate) {
         self.provider.create(parent, name, mode, flags, umask, reply)
     }
-    fn read(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, size: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyData) {
+    fn read(&mut self, req: &Request<'_>, ino: u64, _fh: u64, offset: i64, size: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyData) {
         if ino == FUSE_READY_INO {
             let data = self.mount_time_ms.to_string().into_bytes();
             let start = std::cmp::min(offset as usize, data.len());
@@ -88,10 +91,13 @@ impl Filesystem for FuseFS {
             reply.data(&data[start..end]);
             return;
         }
-        self.provider.read(ino, offset, size, reply)
+        self.provider.read(req.uid(), req.gid(), ino, offset, size, reply)
+    }
+    fn write(&mut self, req: &Request<'_>, ino: u64, _fh: u64, offset: i64, data: &[u8], _write_flags: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyWrite) {
+        self.provider.write(req.uid(), req.gid(), ino, offset, data, reply)
     }
-    fn write(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, data: &[u8], _write_flags: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyWrite) {
-        self.provider.write(ino, offset, data, reply)
+    fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: fuser::ReplyEmpty) {
+        self.provider.access(req.uid(), req.gid(), ino, mask, reply)
     }
     fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
         self.provider.unlink(parent, name, reply)
@@ -99,4 +105,31 @@ impl Filesystem for FuseFS {
     fn rename(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, flags: u32, reply: fuser::ReplyEmpty) {
         self.provider.rename(parent, name, newparent, newname, flags, reply)
     }
+    fn destroy(&mut self) {
+        self.provider.destroy()
+    }
+    fn statfs(&mut self, _req: &Request<'_>, ino: u64, reply: fuser::ReplyStatfs) {
+        self.provider.statfs(ino, reply)
+    }
+    fn setxattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, value: &[u8], flags: i32, _position: u32, reply: fuser::ReplyEmpty) {
+        self.provider.setxattr(ino, name, value, flags, reply)
+    }
+    fn getxattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, size: u32, reply: fuser::ReplyXattr) {
+        self.provider.getxattr(ino, name, size, reply)
+    }
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        self.provider.listxattr(ino, size, reply)
+    }
+    fn removexattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        self.provider.removexattr(ino, name, reply)
+    }
+    fn mknod(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, umask: u32, rdev: u32, reply: ReplyEntry) {
+        self.provider.mknod(parent, name, mode, umask, rdev, reply)
+    }
+    fn link(&mut self, _req: &Request<'_>, ino: u64, newparent: u64, newname: &OsStr, reply: ReplyEntry) {
+        self.provider.link(ino, newparent, newname, reply)
+    }
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+        self.provider.forget(ino, nlookup)
+    }
 } 
\ No newline at end of file
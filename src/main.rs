@@ -7,17 +7,238 @@ use std::ffi::OsStr;
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::fs;
+use std::io::Write as _;
 use log::info;
 use simplelog::*;
+use serde::{Serialize, Deserialize};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::thread;
+
+mod fusefs;
+mod providers;
 
 const TTL: Duration = Duration::from_secs(1); // 1 second
 const ROOT_INODE: u64 = 1;
+const BLOCK_SIZE: u64 = 512;
+/// Total space reported by `statfs`; purely advisory since the backing store
+/// is an in-memory `HashMap`, but lets `df`/`stat -f` show sane numbers.
+const DEFAULT_CAPACITY_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Number of `BLOCK_SIZE` blocks needed to hold `size` bytes, as the fossil
+/// mount computes it: `ceil(size / blksize)`.
+fn blocks_for(size: u64) -> u64 {
+    (size + BLOCK_SIZE - 1) / BLOCK_SIZE
+}
+
+/// Minimal serializable mirror of `fuser::FileAttr`, since the upstream type
+/// doesn't derive Serialize/Deserialize.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct PersistedAttr {
+    ino: u64,
+    size: u64,
+    kind: u8, // 0=file, 1=dir, 2=symlink
+    perm: u16,
+    nlink: u32,
+    uid: u32,
+    gid: u32,
+}
+
+impl PersistedAttr {
+    fn from_attr(attr: &fuser::FileAttr) -> Self {
+        let kind = match attr.kind {
+            fuser::FileType::Directory => 1,
+            fuser::FileType::Symlink => 2,
+            _ => 0,
+        };
+        Self { ino: attr.ino, size: attr.size, kind, perm: attr.perm, nlink: attr.nlink, uid: attr.uid, gid: attr.gid }
+    }
+
+    fn to_attr(&self) -> fuser::FileAttr {
+        let kind = match self.kind {
+            1 => fuser::FileType::Directory,
+            2 => fuser::FileType::Symlink,
+            _ => fuser::FileType::RegularFile,
+        };
+        fuser::FileAttr {
+            ino: self.ino,
+            size: self.size,
+            blocks: blocks_for(self.size),
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm: self.perm,
+            nlink: self.nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            flags: 0,
+            blksize: 512,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum PersistedNode {
+    File { name: String, hash: String, attr: PersistedAttr },
+    Dir { name: String, children: BTreeMap<String, u64>, attr: PersistedAttr },
+    Symlink { name: String, target: PathBuf, attr: PersistedAttr },
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Manifest {
+    next_inode: u64,
+    inodes: HashMap<u64, PersistedNode>,
+}
+
+/// Content-addressed blob store: file contents live under `<dir>/<blake3-hex>`
+/// so identical file bodies are written and kept on disk only once.
+#[derive(Debug, Clone)]
+struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    fn open(dir: PathBuf) -> std::io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(hash)
+    }
+
+    fn write_blob(&self, data: &[u8]) -> String {
+        let hash = blake3::hash(data).to_hex().to_string();
+        let path = self.blob_path(&hash);
+        if !path.exists() {
+            let _ = fs::write(&path, data);
+        }
+        hash
+    }
+
+    fn read_blob(&self, hash: &str) -> Vec<u8> {
+        fs::read(self.blob_path(hash)).unwrap_or_default()
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.dir.join("manifest")
+    }
+
+    fn load_manifest(&self) -> Option<Manifest> {
+        let bytes = fs::read(self.manifest_path()).ok()?;
+        bincode::deserialize(&bytes).ok()
+    }
+
+    fn save_manifest(&self, manifest: &Manifest) {
+        if let Ok(bytes) = bincode::serialize(manifest) {
+            if let Ok(mut f) = fs::File::create(self.manifest_path()) {
+                let _ = f.write_all(&bytes);
+            }
+        }
+    }
+}
+
+/// A reference to one content-defined chunk held in `Inner::chunk_store`.
+type ChunkRef = [u8; 32];
+
+const CHUNK_MIN: usize = 2 * 1024;
+const CHUNK_TARGET: usize = 16 * 1024;
+const CHUNK_MAX: usize = 64 * 1024;
+
+/// Deterministic Gear-hash table, lazily built from a fixed seed so every run
+/// of MemFS chunks identical input identically.
+fn gear_table() -> &'static [u64; 256] {
+    static TABLE: OnceLock<[u64; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u64; 256];
+        let mut seed: u64 = 0x9E3779B97F4A7C15;
+        for slot in table.iter_mut() {
+            // splitmix64
+            seed = seed.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            *slot = z ^ (z >> 31);
+        }
+        table
+    })
+}
+
+/// Split `data` into content-defined chunks using a Gear-hash rolling sum:
+/// a boundary falls wherever the low bits of the hash are zero once the
+/// chunk has grown past `CHUNK_MIN`, so inserting or removing bytes only
+/// perturbs the chunks touching the edit. Chunks are clamped to
+/// `[CHUNK_MIN, CHUNK_MAX]`.
+fn content_defined_chunks(data: &[u8]) -> Vec<&[u8]> {
+    if data.is_empty() {
+        return vec![];
+    }
+    let table = gear_table();
+    let mask: u64 = (1u64 << 14) - 1; // ~16 KiB average chunk size
+    let mut chunks = Vec::new();
+    let mut start = 0usize;
+    let mut hash: u64 = 0;
+    for i in 0..data.len() {
+        hash = hash.wrapping_shl(1).wrapping_add(table[data[i] as usize]);
+        let len = i - start + 1;
+        if (len >= CHUNK_MIN && hash & mask == 0) || len >= CHUNK_MAX {
+            chunks.push(&data[start..=i]);
+            start = i + 1;
+            hash = 0;
+        }
+    }
+    if start < data.len() {
+        chunks.push(&data[start..]);
+    }
+    chunks
+}
+
+/// Hash, intern and refcount each content-defined chunk of `data` into
+/// `store`, returning the ordered list of chunk hashes that make up the file.
+fn chunk_and_intern(store: &mut HashMap<ChunkRef, (Arc<Vec<u8>>, u32)>, data: &[u8]) -> Vec<ChunkRef> {
+    let mut refs = Vec::new();
+    for piece in content_defined_chunks(data) {
+        let hash: ChunkRef = *blake3::hash(piece).as_bytes();
+        store
+            .entry(hash)
+            .and_modify(|(_, refcount)| *refcount += 1)
+            .or_insert_with(|| (Arc::new(piece.to_vec()), 1));
+        refs.push(hash);
+    }
+    refs
+}
+
+/// Decrement refcounts for a file's chunks, evicting any that drop to zero.
+fn release_chunks(store: &mut HashMap<ChunkRef, (Arc<Vec<u8>>, u32)>, refs: &[ChunkRef]) {
+    for hash in refs {
+        if let Some((_, refcount)) = store.get_mut(hash) {
+            *refcount -= 1;
+            if *refcount == 0 {
+                store.remove(hash);
+            }
+        }
+    }
+}
+
+/// Reassemble a file's bytes by concatenating its chunks in order.
+fn assemble_chunks(store: &HashMap<ChunkRef, (Arc<Vec<u8>>, u32)>, refs: &[ChunkRef]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for hash in refs {
+        if let Some((bytes, _)) = store.get(hash) {
+            data.extend_from_slice(bytes);
+        }
+    }
+    data
+}
 
 #[derive(Debug, Clone)]
 struct InMemoryFile {
     name: String,
-    data: Vec<u8>,
+    chunks: Vec<ChunkRef>,
     attr: fuser::FileAttr,
+    xattrs: BTreeMap<String, Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
@@ -25,23 +246,121 @@ struct InMemoryDir {
     name: String,
     children: BTreeMap<String, u64>, // name -> inode
     attr: fuser::FileAttr,
+    xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+struct InMemorySymlink {
+    target: PathBuf,
+    attr: fuser::FileAttr,
 }
 
+/// All filesystem state, guarded by a single `RwLock` so FUSE callbacks
+/// dispatched onto the worker pool can run concurrent reads (readdir/read/
+/// getattr/...) against each other while writes take exclusive access.
 #[derive(Debug)]
-struct MemFS {
+struct Inner {
     inodes: HashMap<u64, Node>,
+    /// Inode <-> path cache, kept up to date on mkdir/create/symlink/unlink/rmdir
+    /// so callers don't need to re-walk `inodes` to resolve a path. Tracks only
+    /// one path per inode, so it's only authoritative until the first hard link.
     paths: HashMap<PathBuf, u64>,
+    path_of: HashMap<u64, PathBuf>,
     next_inode: u64,
+    store: Option<Store>,
+    /// Global content-addressed dedup store shared by every file's chunk list.
+    chunk_store: HashMap<ChunkRef, (Arc<Vec<u8>>, u32)>,
+    /// Total space `statfs` reports the filesystem as having, in bytes.
+    capacity_bytes: u64,
+}
+
+/// Job queue backing FUSE's dispatch: each `Filesystem` callback pushes one
+/// closure here and returns immediately, so the kernel can issue the next
+/// request while this one is still being served on a worker thread.
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct WorkerPool {
+    sender: std::sync::mpsc::Sender<Job>,
+}
+
+impl WorkerPool {
+    fn new(size: usize) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..size {
+            let receiver = Arc::clone(&receiver);
+            thread::spawn(move || loop {
+                let job = { receiver.lock().unwrap().recv() };
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break, // sender dropped, shut down
+                }
+            });
+        }
+        Self { sender }
+    }
+
+    fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        // The pool outlives every `MemFS` clone for the life of the mount, so
+        // a send failure would mean all worker threads panicked; nothing
+        // useful to do with the reply in that case.
+        let _ = self.sender.send(Box::new(job));
+    }
+}
+
+struct MemFS {
+    inner: Arc<RwLock<Inner>>,
+    pool: Arc<WorkerPool>,
 }
 
 #[derive(Debug, Clone)]
 enum Node {
     File(InMemoryFile),
     Dir(InMemoryDir),
+    Symlink(InMemorySymlink),
 }
 
-impl MemFS {
-    fn new() -> Self {
+impl Node {
+    fn attr(&self) -> fuser::FileAttr {
+        match self {
+            Node::File(f) => f.attr,
+            Node::Dir(d) => d.attr,
+            Node::Symlink(s) => s.attr,
+        }
+    }
+}
+
+/// POSIX owner/group/other permission check, modeled on the classic
+/// `check_access` used by FUSE reference filesystems: root bypasses checks
+/// entirely, otherwise the owner/group/other bits are selected based on
+/// whether the requester matches the node's uid/gid, and the requested
+/// `mask` (some combination of `R_OK`/`W_OK`/`X_OK`) must be fully covered.
+fn check_access(node_uid: u32, node_gid: u32, node_perm: u16, req_uid: u32, req_gid: u32, mask: i32) -> bool {
+    if req_uid == 0 {
+        return true;
+    }
+    let mask = mask & (libc::R_OK | libc::W_OK | libc::X_OK);
+    if mask == 0 {
+        return true;
+    }
+    let perm_bits = if req_uid == node_uid {
+        (node_perm >> 6) & 0o7
+    } else if req_gid == node_gid {
+        (node_perm >> 3) & 0o7
+    } else {
+        node_perm & 0o7
+    } as i32;
+    perm_bits & mask == mask
+}
+
+impl Inner {
+    /// Build a fresh tree, or restore one from `store` if it already holds a manifest.
+    fn new_with_store(store: Option<Store>, capacity_bytes: u64) -> Self {
+        if let Some(store) = &store {
+            if let Some(manifest) = store.load_manifest() {
+                return Self::from_manifest(manifest, store.clone(), capacity_bytes);
+            }
+        }
         let mut inodes = HashMap::new();
         let mut paths = HashMap::new();
         let root_attr = fuser::FileAttr {
@@ -65,10 +384,75 @@ impl MemFS {
             name: "/".to_string(),
             children: BTreeMap::new(),
             attr: root_attr,
+            xattrs: BTreeMap::new(),
         });
         inodes.insert(ROOT_INODE, root);
         paths.insert(PathBuf::from("/"), ROOT_INODE);
-        Self { inodes, paths, next_inode: ROOT_INODE + 1 }
+        let mut path_of = HashMap::new();
+        path_of.insert(ROOT_INODE, PathBuf::from("/"));
+        Self { inodes, paths, path_of, next_inode: ROOT_INODE + 1, store, chunk_store: HashMap::new(), capacity_bytes }
+    }
+
+    fn from_manifest(manifest: Manifest, store: Store, capacity_bytes: u64) -> Self {
+        let mut inodes = HashMap::new();
+        let mut chunk_store = HashMap::new();
+        for (ino, node) in manifest.inodes {
+            let node = match node {
+                PersistedNode::File { name, hash, attr } => {
+                    let data = store.read_blob(&hash);
+                    let chunks = chunk_and_intern(&mut chunk_store, &data);
+                    Node::File(InMemoryFile { name, chunks, attr: attr.to_attr(), xattrs: BTreeMap::new() })
+                }
+                PersistedNode::Dir { name, children, attr } => {
+                    Node::Dir(InMemoryDir { name, children, attr: attr.to_attr(), xattrs: BTreeMap::new() })
+                }
+                PersistedNode::Symlink { name: _, target, attr } => {
+                    Node::Symlink(InMemorySymlink { target, attr: attr.to_attr() })
+                }
+            };
+            inodes.insert(ino, node);
+        }
+        let mut path_of = HashMap::new();
+        path_of.insert(ROOT_INODE, PathBuf::from("/"));
+        let mut paths = HashMap::new();
+        paths.insert(PathBuf::from("/"), ROOT_INODE);
+        Self { inodes, paths, path_of, next_inode: manifest.next_inode, store: Some(store), chunk_store, capacity_bytes }
+    }
+
+    /// Record that `name` inside `parent` now resolves to `ino`, keeping the
+    /// inode<->path cache in sync with the tree.
+    fn track_path(&mut self, parent: u64, name: &str, ino: u64) {
+        let Some(parent_path) = self.path_of.get(&parent) else { return };
+        let path = parent_path.join(name);
+        self.path_of.insert(ino, path.clone());
+        self.paths.insert(path, ino);
+    }
+
+    /// Write every file's bytes to the content-addressed store and persist the tree manifest.
+    fn save_snapshot(&self) {
+        let Some(store) = &self.store else { return };
+        let mut manifest = Manifest { next_inode: self.next_inode, inodes: HashMap::new() };
+        for (&ino, node) in &self.inodes {
+            let persisted = match node {
+                Node::File(f) => {
+                    let data = assemble_chunks(&self.chunk_store, &f.chunks);
+                    let hash = store.write_blob(&data);
+                    PersistedNode::File { name: f.name.clone(), hash, attr: PersistedAttr::from_attr(&f.attr) }
+                }
+                Node::Dir(d) => PersistedNode::Dir {
+                    name: d.name.clone(),
+                    children: d.children.clone(),
+                    attr: PersistedAttr::from_attr(&d.attr),
+                },
+                Node::Symlink(s) => PersistedNode::Symlink {
+                    name: String::new(),
+                    target: s.target.clone(),
+                    attr: PersistedAttr::from_attr(&s.attr),
+                },
+            };
+            manifest.inodes.insert(ino, persisted);
+        }
+        store.save_manifest(&manifest);
     }
 
     fn alloc_inode(&mut self) -> u64 {
@@ -78,34 +462,212 @@ impl MemFS {
     }
 }
 
+/// Worker threads a MemFS instance dispatches FUSE callbacks onto; sized
+/// like a small thread pool rather than one-thread-per-request since inode
+/// state is shared and serialized by `Inner`'s `RwLock` regardless.
+const DISPATCH_THREADS: usize = 4;
+
+impl MemFS {
+    fn new() -> Self {
+        Self::new_with_store(None)
+    }
+
+    fn new_with_store(store: Option<Store>) -> Self {
+        Self::new_with_store_and_capacity(store, DEFAULT_CAPACITY_BYTES)
+    }
+
+    fn new_with_store_and_capacity(store: Option<Store>, capacity_bytes: u64) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Inner::new_with_store(store, capacity_bytes))),
+            pool: Arc::new(WorkerPool::new(DISPATCH_THREADS)),
+        }
+    }
+}
+
 impl Filesystem for MemFS {
     // Return ENOSYS for all unimplemented methods
     fn rename(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _newparent: u64, _newname: &OsStr, _flags: u32, reply: fuser::ReplyEmpty) {
         reply.error(libc::ENOSYS);
     }
-    fn link(&mut self, _req: &Request<'_>, _ino: u64, _newparent: u64, _newname: &OsStr, reply: fuser::ReplyEntry) {
-        reply.error(libc::ENOSYS);
+    fn link(&mut self, _req: &Request<'_>, ino: u64, newparent: u64, newname: &OsStr, reply: fuser::ReplyEntry) {
+        let newname_str = newname.to_str().unwrap_or("").to_string();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            let already_exists = if let Some(Node::Dir(dir)) = inner.inodes.get(&newparent) {
+                dir.children.contains_key(&newname_str)
+            } else {
+                reply.error(ENOENT);
+                return;
+            };
+            if already_exists {
+                reply.error(libc::EEXIST);
+                return;
+            }
+            let attr = match inner.inodes.get_mut(&ino) {
+                Some(Node::File(f)) => {
+                    f.attr.nlink += 1;
+                    f.attr
+                }
+                Some(Node::Dir(_)) | Some(Node::Symlink(_)) | None => {
+                    reply.error(libc::EPERM);
+                    return;
+                }
+            };
+            if let Some(Node::Dir(dir)) = inner.inodes.get_mut(&newparent) {
+                dir.children.insert(newname_str, ino);
+            }
+            reply.entry(&TTL, &attr, 0);
+        });
     }
-    fn unlink(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, reply: fuser::ReplyEmpty) {
-        reply.error(libc::ENOSYS);
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        let name_str = name.to_str().unwrap_or("").to_string();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            let target_ino = if let Some(Node::Dir(parent_dir)) = inner.inodes.get(&parent) {
+                parent_dir.children.get(&name_str).copied()
+            } else {
+                reply.error(ENOENT);
+                return;
+            };
+            let ino = match target_ino {
+                Some(ino) => ino,
+                None => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+            let drop_inode = match inner.inodes.get_mut(&ino) {
+                Some(Node::File(f)) => {
+                    f.attr.nlink = f.attr.nlink.saturating_sub(1);
+                    f.attr.nlink == 0
+                }
+                Some(Node::Symlink(_)) => true,
+                Some(Node::Dir(_)) => {
+                    reply.error(libc::EISDIR);
+                    return;
+                }
+                None => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+            if let Some(Node::Dir(parent_dir)) = inner.inodes.get_mut(&parent) {
+                parent_dir.children.remove(&name_str);
+            }
+            if drop_inode {
+                if let Some(Node::File(f)) = inner.inodes.remove(&ino) {
+                    release_chunks(&mut inner.chunk_store, &f.chunks);
+                }
+                inner.path_of.remove(&ino).map(|p| inner.paths.remove(&p));
+            }
+            reply.ok();
+        });
     }
     fn mknod(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _mode: u32, _rdev: u32, _flags: u32, reply: fuser::ReplyEntry) {
         reply.error(libc::ENOSYS);
     }
-    fn symlink(&mut self, _req: &Request<'_>, _parent: u64, _name: &OsStr, _link: &std::path::Path, reply: fuser::ReplyEntry) {
-        reply.error(libc::ENOSYS);
+    fn symlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, link: &std::path::Path, reply: fuser::ReplyEntry) {
+        let name_str = name.to_str().unwrap_or("").to_string();
+        let target = link.to_path_buf();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            let already_exists = if let Some(Node::Dir(dir)) = inner.inodes.get(&parent) {
+                dir.children.contains_key(&name_str)
+            } else {
+                reply.error(ENOENT);
+                return;
+            };
+            if already_exists {
+                reply.error(libc::EEXIST);
+                return;
+            }
+            let ino = inner.alloc_inode();
+            let attr = fuser::FileAttr {
+                ino,
+                size: target.as_os_str().len() as u64,
+                blocks: 0,
+                atime: UNIX_EPOCH,
+                mtime: UNIX_EPOCH,
+                ctime: UNIX_EPOCH,
+                crtime: UNIX_EPOCH,
+                kind: fuser::FileType::Symlink,
+                perm: 0o777,
+                nlink: 1,
+                uid: unsafe { libc::geteuid() },
+                gid: unsafe { libc::getegid() },
+                rdev: 0,
+                flags: 0,
+                blksize: 512,
+            };
+            let symlink_node = Node::Symlink(InMemorySymlink { target, attr });
+            if let Some(Node::Dir(dir)) = inner.inodes.get_mut(&parent) {
+                dir.children.insert(name_str.clone(), ino);
+            }
+            inner.inodes.insert(ino, symlink_node);
+            inner.track_path(parent, &name_str, ino);
+            reply.entry(&TTL, &attr, 0);
+        });
     }
-    fn readlink(&mut self, _req: &Request<'_>, _ino: u64, reply: fuser::ReplyData) {
-        reply.error(libc::ENOSYS);
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: fuser::ReplyData) {
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            if let Some(Node::Symlink(s)) = inner.inodes.get(&ino) {
+                reply.data(s.target.as_os_str().as_encoded_bytes());
+            } else {
+                reply.error(libc::EINVAL);
+            }
+        });
     }
     fn fsync(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _datasync: bool, reply: fuser::ReplyEmpty) {
-        reply.error(libc::ENOSYS);
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            inner.read().unwrap().save_snapshot();
+            reply.ok();
+        });
     }
-    fn fallocate(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _length: i64, _mode: i32, reply: fuser::ReplyEmpty) {
-        reply.error(libc::ENOSYS);
+    fn fallocate(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, length: i64, mode: i32, reply: fuser::ReplyEmpty) {
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            let Some(Node::File(file)) = inner.inodes.get(&ino) else {
+                reply.error(ENOENT);
+                return;
+            };
+            let target_len = (offset + length).max(0) as usize;
+            let mut data = assemble_chunks(&inner.chunk_store, &file.chunks);
+            let old_chunks = file.chunks.clone();
+            if data.len() < target_len {
+                data.resize(target_len, 0);
+            }
+            release_chunks(&mut inner.chunk_store, &old_chunks);
+            let new_chunks = chunk_and_intern(&mut inner.chunk_store, &data);
+            if let Some(Node::File(file)) = inner.inodes.get_mut(&ino) {
+                file.chunks = new_chunks;
+                // FALLOC_FL_KEEP_SIZE preallocates space without growing the
+                // reported file size; `blocks` still reflects what's backing it.
+                if mode & libc::FALLOC_FL_KEEP_SIZE == 0 && data.len() as u64 > file.attr.size {
+                    file.attr.size = data.len() as u64;
+                }
+                file.attr.blocks = blocks_for(data.len() as u64);
+            }
+            reply.ok();
+        });
     }
     fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: fuser::ReplyStatfs) {
-        reply.error(libc::ENOSYS);
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            let used_bytes: u64 = inner.inodes.values().map(|n| n.attr().size).sum();
+            let total_blocks = inner.capacity_bytes / BLOCK_SIZE;
+            let used_blocks = (used_bytes + BLOCK_SIZE - 1) / BLOCK_SIZE;
+            let free_blocks = total_blocks.saturating_sub(used_blocks);
+            let files = inner.inodes.len() as u64;
+            reply.statfs(total_blocks, free_blocks, free_blocks, files, 0, BLOCK_SIZE as u32, 255, BLOCK_SIZE as u32);
+        });
     }
     fn opendir(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
         reply.error(libc::ENOSYS);
@@ -116,20 +678,121 @@ impl Filesystem for MemFS {
     fn fsyncdir(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _datasync: bool, reply: fuser::ReplyEmpty) {
         reply.error(libc::ENOSYS);
     }
-    fn getxattr(&mut self, _req: &Request<'_>, _ino: u64, _name: &OsStr, _size: u32, reply: fuser::ReplyXattr) {
-        reply.error(libc::ENOSYS);
+    fn getxattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, size: u32, reply: fuser::ReplyXattr) {
+        let name = name.to_string_lossy().into_owned();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            let xattrs = match inner.inodes.get(&ino) {
+                Some(Node::File(f)) => &f.xattrs,
+                Some(Node::Dir(d)) => &d.xattrs,
+                _ => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+            match xattrs.get(&name) {
+                Some(value) => {
+                    if size == 0 {
+                        reply.size(value.len() as u32);
+                    } else if value.len() > size as usize {
+                        reply.error(libc::ERANGE);
+                    } else {
+                        reply.data(value);
+                    }
+                }
+                None => reply.error(libc::ENODATA),
+            }
+        });
     }
-    fn setxattr(&mut self, _req: &Request<'_>, _ino: u64, _name: &OsStr, _value: &[u8], _flags: i32, _position: u32, reply: fuser::ReplyEmpty) {
-        reply.error(libc::ENOSYS);
+    fn setxattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, value: &[u8], flags: i32, _position: u32, reply: fuser::ReplyEmpty) {
+        let name = name.to_string_lossy().into_owned();
+        let value = value.to_vec();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            let xattrs = match inner.inodes.get_mut(&ino) {
+                Some(Node::File(f)) => &mut f.xattrs,
+                Some(Node::Dir(d)) => &mut d.xattrs,
+                _ => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+            let exists = xattrs.contains_key(&name);
+            if flags & libc::XATTR_CREATE != 0 && exists {
+                reply.error(libc::EEXIST);
+                return;
+            }
+            if flags & libc::XATTR_REPLACE != 0 && !exists {
+                reply.error(libc::ENODATA);
+                return;
+            }
+            xattrs.insert(name, value);
+            reply.ok();
+        });
     }
-    fn listxattr(&mut self, _req: &Request<'_>, _ino: u64, _size: u32, reply: fuser::ReplyXattr) {
-        reply.error(libc::ENOSYS);
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            let xattrs = match inner.inodes.get(&ino) {
+                Some(Node::File(f)) => &f.xattrs,
+                Some(Node::Dir(d)) => &d.xattrs,
+                _ => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+            let mut names = Vec::new();
+            for key in xattrs.keys() {
+                names.extend_from_slice(key.as_bytes());
+                names.push(0);
+            }
+            if size == 0 {
+                reply.size(names.len() as u32);
+            } else if names.len() > size as usize {
+                reply.error(libc::ERANGE);
+            } else {
+                reply.data(&names);
+            }
+        });
     }
-    fn removexattr(&mut self, _req: &Request<'_>, _ino: u64, _name: &OsStr, reply: fuser::ReplyEmpty) {
-        reply.error(libc::ENOSYS);
+    fn removexattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        let name = name.to_string_lossy().into_owned();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            let xattrs = match inner.inodes.get_mut(&ino) {
+                Some(Node::File(f)) => &mut f.xattrs,
+                Some(Node::Dir(d)) => &mut d.xattrs,
+                _ => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+            match xattrs.remove(&name) {
+                Some(_) => reply.ok(),
+                None => reply.error(libc::ENODATA),
+            }
+        });
     }
-    fn access(&mut self, _req: &Request<'_>, _ino: u64, _mask: i32, reply: fuser::ReplyEmpty) {
-        reply.error(libc::ENOSYS);
+    fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: fuser::ReplyEmpty) {
+        let (req_uid, req_gid) = (req.uid(), req.gid());
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            if let Some(node) = inner.inodes.get(&ino) {
+                let attr = node.attr();
+                if check_access(attr.uid, attr.gid, attr.perm, req_uid, req_gid, mask) {
+                    reply.ok();
+                } else {
+                    reply.error(libc::EACCES);
+                }
+            } else {
+                reply.error(ENOENT);
+            }
+        });
     }
     fn bmap(&mut self, _req: &Request<'_>, _ino: u64, _blocksize: u32, _idx: u64, reply: fuser::ReplyBmap) {
         reply.error(libc::ENOSYS);
@@ -138,14 +801,49 @@ impl Filesystem for MemFS {
         reply.error(libc::ENOSYS);
     }
 
-    fn copy_file_range(&mut self, _req: &Request<'_>, _ino_in: u64, _fh_in: u64, _offset_in: i64, _ino_out: u64, _fh_out: u64, _offset_out: i64, _len: u64, _flags: u32, reply: fuser::ReplyWrite) {
-        reply.error(libc::ENOSYS);
+    fn copy_file_range(&mut self, _req: &Request<'_>, ino_in: u64, _fh_in: u64, offset_in: i64, ino_out: u64, _fh_out: u64, offset_out: i64, len: u64, _flags: u32, reply: fuser::ReplyWrite) {
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            let src_data = match inner.inodes.get(&ino_in) {
+                Some(Node::File(f)) => assemble_chunks(&inner.chunk_store, &f.chunks),
+                _ => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+            let src_start = std::cmp::min(offset_in.max(0) as usize, src_data.len());
+            let src_end = std::cmp::min(src_start + len as usize, src_data.len());
+            let copied = src_data[src_start..src_end].to_vec();
+
+            let old_chunks = match inner.inodes.get(&ino_out) {
+                Some(Node::File(f)) => f.chunks.clone(),
+                _ => {
+                    reply.error(ENOENT);
+                    return;
+                }
+            };
+            let mut dst_data = assemble_chunks(&inner.chunk_store, &old_chunks);
+            let dst_offset = offset_out.max(0) as usize;
+            if dst_data.len() < dst_offset + copied.len() {
+                dst_data.resize(dst_offset + copied.len(), 0);
+            }
+            dst_data[dst_offset..dst_offset + copied.len()].copy_from_slice(&copied);
+            release_chunks(&mut inner.chunk_store, &old_chunks);
+            let new_chunks = chunk_and_intern(&mut inner.chunk_store, &dst_data);
+            if let Some(Node::File(dst)) = inner.inodes.get_mut(&ino_out) {
+                dst.chunks = new_chunks;
+                dst.attr.size = dst_data.len() as u64;
+                dst.attr.blocks = blocks_for(dst.attr.size);
+            }
+            reply.written(copied.len() as u32);
+        });
     }
     fn lseek(&mut self, _req: &Request<'_>, _ino: u64, _fh: u64, _offset: i64, _whence: i32, reply: fuser::ReplyLseek) {
         reply.error(libc::ENOSYS);
     }
     fn destroy(&mut self) {
-        // No-op
+        self.inner.read().unwrap().save_snapshot();
     }
     fn init(&mut self, _req: &Request<'_>, _config: &mut fuser::KernelConfig) -> Result<(), i32> {
         Ok(())
@@ -160,61 +858,90 @@ impl Filesystem for MemFS {
         reply.error(libc::ENOSYS);
     }
     fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &std::ffi::OsStr, reply: fuser::ReplyEmpty) {
-        let name_str = name.to_str().unwrap_or("");
-        // Find parent directory
-        let target_ino = if let Some(Node::Dir(parent_dir)) = self.inodes.get(&parent) {
-            parent_dir.children.get(name_str).copied()
-        } else {
-            reply.error(libc::ENOENT); // Parent not found
-            return;
-        };
-        let ino = match target_ino {
-            Some(ino) => ino,
-            None => {
-                reply.error(libc::ENOENT); // Entry not found
+        let name_str = name.to_str().unwrap_or("").to_string();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            // Find parent directory
+            let target_ino = if let Some(Node::Dir(parent_dir)) = inner.inodes.get(&parent) {
+                parent_dir.children.get(&name_str).copied()
+            } else {
+                reply.error(libc::ENOENT); // Parent not found
+                return;
+            };
+            let ino = match target_ino {
+                Some(ino) => ino,
+                None => {
+                    reply.error(libc::ENOENT); // Entry not found
+                    return;
+                }
+            };
+            // Check if the inode is a directory and is empty
+            let is_empty_dir = if let Some(Node::Dir(dir)) = inner.inodes.get(&ino) {
+                dir.children.is_empty()
+            } else {
+                reply.error(libc::ENOTDIR); // Not a directory
+                return;
+            };
+            if !is_empty_dir {
+                reply.error(libc::ENOTEMPTY); // Directory not empty
                 return;
             }
-        };
-        // Check if the inode is a directory and is empty
-        let is_empty_dir = if let Some(Node::Dir(dir)) = self.inodes.get(&ino) {
-            dir.children.is_empty()
-        } else {
-            reply.error(libc::ENOTDIR); // Not a directory
-            return;
-        };
-        if !is_empty_dir {
-            reply.error(libc::ENOTEMPTY); // Directory not empty
-            return;
-        }
-        // Now remove from parent's children and from inode map
-        if let Some(Node::Dir(parent_dir)) = self.inodes.get_mut(&parent) {
-            parent_dir.children.remove(name_str);
-        }
-        self.inodes.remove(&ino);
-        reply.ok();
+            // Now remove from parent's children and from inode map
+            if let Some(Node::Dir(parent_dir)) = inner.inodes.get_mut(&parent) {
+                parent_dir.children.remove(&name_str);
+            }
+            inner.inodes.remove(&ino);
+            inner.path_of.remove(&ino).map(|p| inner.paths.remove(&p));
+            reply.ok();
+        });
     }
-    fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
-        if self.inodes.contains_key(&ino) {
-            reply.opened(0, 0);
-        } else {
-            reply.error(ENOENT);
-        }
+    fn open(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        let (req_uid, req_gid) = (req.uid(), req.gid());
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            if let Some(node) = inner.inodes.get(&ino) {
+                let attr = node.attr();
+                let mask = match flags & libc::O_ACCMODE {
+                    libc::O_WRONLY => libc::W_OK,
+                    libc::O_RDWR => libc::R_OK | libc::W_OK,
+                    _ => libc::R_OK,
+                };
+                if check_access(attr.uid, attr.gid, attr.perm, req_uid, req_gid, mask) {
+                    reply.opened(0, 0);
+                } else {
+                    reply.error(libc::EACCES);
+                }
+            } else {
+                reply.error(ENOENT);
+            }
+        });
     }
 
     fn flush(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, _lock_owner: u64, reply: fuser::ReplyEmpty) {
-        if self.inodes.contains_key(&ino) {
-            reply.ok();
-        } else {
-            reply.error(ENOENT);
-        }
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            if inner.inodes.contains_key(&ino) {
+                inner.save_snapshot();
+                reply.ok();
+            } else {
+                reply.error(ENOENT);
+            }
+        });
     }
 
     fn release(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, _flags: i32, _lock_owner: Option<u64>, _flush: bool, reply: fuser::ReplyEmpty) {
-        if self.inodes.contains_key(&ino) {
-            reply.ok();
-        } else {
-            reply.error(ENOENT);
-        }
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            if inner.inodes.contains_key(&ino) {
+                reply.ok();
+            } else {
+                reply.error(ENOENT);
+            }
+        });
     }
 
     fn setattr(&mut self, _req: &Request<'_>, ino: u64, mode: Option<u32>, uid: Option<u32>, gid: Option<u32>, size: Option<u64>, atime: Option<fuser::TimeOrNow>, mtime: Option<fuser::TimeOrNow>, ctime: Option<std::time::SystemTime>, fh: Option<u64>, crtime: Option<std::time::SystemTime>, chgtime: Option<std::time::SystemTime>, bkuptime: Option<std::time::SystemTime>, flags: Option<u32>, reply: ReplyAttr) {
@@ -224,199 +951,277 @@ impl Filesystem for MemFS {
                 fuser::TimeOrNow::Now => std::time::SystemTime::now(),
             }
         }
-        if let Some(node) = self.inodes.get_mut(&ino) {
-            match node {
-                Node::File(f) => {
-                    if let Some(new_size) = size {
-                        f.data.resize(new_size as usize, 0);
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            if size.is_some() {
+                if let Some(Node::File(f)) = inner.inodes.get(&ino) {
+                    let new_size = size.unwrap();
+                    let mut data = assemble_chunks(&inner.chunk_store, &f.chunks);
+                    data.resize(new_size as usize, 0);
+                    let old_chunks = f.chunks.clone();
+                    release_chunks(&mut inner.chunk_store, &old_chunks);
+                    let new_chunks = chunk_and_intern(&mut inner.chunk_store, &data);
+                    if let Some(Node::File(f)) = inner.inodes.get_mut(&ino) {
+                        f.chunks = new_chunks;
                         f.attr.size = new_size;
+                        f.attr.blocks = blocks_for(new_size);
                     }
-                    if let Some(m) = mode { f.attr.perm = m as u16; }
-                    if let Some(u) = uid { f.attr.uid = u; }
-                    if let Some(g) = gid { f.attr.gid = g; }
-                    if let Some(a) = atime { f.attr.atime = timeornow_to_systemtime(a); }
-                    if let Some(m) = mtime { f.attr.mtime = timeornow_to_systemtime(m); }
-                    if let Some(c) = ctime { f.attr.ctime = c; }
-                    if let Some(cr) = crtime { f.attr.crtime = cr; }
-                    if let Some(fg) = flags { f.attr.flags = fg; }
-                    reply.attr(&TTL, &f.attr);
                 }
-                Node::Dir(d) => {
-                    if let Some(m) = mode { d.attr.perm = m as u16; }
-                    if let Some(u) = uid { d.attr.uid = u; }
-                    if let Some(g) = gid { d.attr.gid = g; }
-                    if let Some(a) = atime { d.attr.atime = timeornow_to_systemtime(a); }
-                    if let Some(m) = mtime { d.attr.mtime = timeornow_to_systemtime(m); }
-                    if let Some(c) = ctime { d.attr.ctime = c; }
-                    if let Some(cr) = crtime { d.attr.crtime = cr; }
-                    if let Some(fg) = flags { d.attr.flags = fg; }
-                    reply.attr(&TTL, &d.attr);
+            }
+            if let Some(node) = inner.inodes.get_mut(&ino) {
+                match node {
+                    Node::File(f) => {
+                        if let Some(m) = mode { f.attr.perm = m as u16; }
+                        if let Some(u) = uid { f.attr.uid = u; }
+                        if let Some(g) = gid { f.attr.gid = g; }
+                        if let Some(a) = atime { f.attr.atime = timeornow_to_systemtime(a); }
+                        if let Some(m) = mtime { f.attr.mtime = timeornow_to_systemtime(m); }
+                        if let Some(c) = ctime { f.attr.ctime = c; }
+                        if let Some(cr) = crtime { f.attr.crtime = cr; }
+                        if let Some(fg) = flags { f.attr.flags = fg; }
+                        reply.attr(&TTL, &f.attr);
+                    }
+                    Node::Dir(d) => {
+                        if let Some(m) = mode { d.attr.perm = m as u16; }
+                        if let Some(u) = uid { d.attr.uid = u; }
+                        if let Some(g) = gid { d.attr.gid = g; }
+                        if let Some(a) = atime { d.attr.atime = timeornow_to_systemtime(a); }
+                        if let Some(m) = mtime { d.attr.mtime = timeornow_to_systemtime(m); }
+                        if let Some(c) = ctime { d.attr.ctime = c; }
+                        if let Some(cr) = crtime { d.attr.crtime = cr; }
+                        if let Some(fg) = flags { d.attr.flags = fg; }
+                        reply.attr(&TTL, &d.attr);
+                    }
+                    Node::Symlink(s) => {
+                        if let Some(m) = mode { s.attr.perm = m as u16; }
+                        if let Some(u) = uid { s.attr.uid = u; }
+                        if let Some(g) = gid { s.attr.gid = g; }
+                        if let Some(a) = atime { s.attr.atime = timeornow_to_systemtime(a); }
+                        if let Some(m) = mtime { s.attr.mtime = timeornow_to_systemtime(m); }
+                        if let Some(c) = ctime { s.attr.ctime = c; }
+                        if let Some(cr) = crtime { s.attr.crtime = cr; }
+                        if let Some(fg) = flags { s.attr.flags = fg; }
+                        reply.attr(&TTL, &s.attr);
+                    }
                 }
+            } else {
+                reply.error(ENOENT);
             }
-        } else {
-            reply.error(ENOENT);
-        }
+        });
     }
-    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        let name = name.to_str().unwrap_or("");
-        let parent_node = self.inodes.get(&parent);
-        if let Some(Node::Dir(dir)) = parent_node {
-            if let Some(&child_ino) = dir.children.get(name) {
-                if let Some(node) = self.inodes.get(&child_ino) {
-                    let attr = match node {
-                        Node::File(f) => f.attr,
-                        Node::Dir(d) => d.attr,
-                    };
-                    reply.entry(&TTL, &attr, 0);
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let name = name.to_str().unwrap_or("").to_string();
+        let (req_uid, req_gid) = (req.uid(), req.gid());
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            let parent_node = inner.inodes.get(&parent);
+            if let Some(Node::Dir(dir)) = parent_node {
+                if !check_access(dir.attr.uid, dir.attr.gid, dir.attr.perm, req_uid, req_gid, libc::X_OK) {
+                    reply.error(libc::EACCES);
                     return;
                 }
+                if let Some(&child_ino) = dir.children.get(&name) {
+                    if let Some(node) = inner.inodes.get(&child_ino) {
+                        let attr = node.attr();
+                        reply.entry(&TTL, &attr, 0);
+                        return;
+                    }
+                }
             }
-        }
-        reply.error(ENOENT);
+            reply.error(ENOENT);
+        });
     }
 
     fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
-        if let Some(node) = self.inodes.get(&ino) {
-            let attr = match node {
-                Node::File(f) => f.attr,
-                Node::Dir(d) => d.attr,
-            };
-            reply.attr(&TTL, &attr);
-        } else {
-            reply.error(ENOENT);
-        }
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            if let Some(node) = inner.inodes.get(&ino) {
+                let attr = match node {
+                    Node::File(f) => f.attr,
+                    Node::Dir(d) => d.attr,
+                    Node::Symlink(s) => s.attr,
+                };
+                reply.attr(&TTL, &attr);
+            } else {
+                reply.error(ENOENT);
+            }
+        });
     }
 
     fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
-        if let Some(Node::Dir(dir)) = self.inodes.get(&ino) {
-            let mut entries = vec![(ROOT_INODE, fuser::FileType::Directory, ".".to_string()), (ROOT_INODE, fuser::FileType::Directory, "..".to_string())];
-            for (name, &child_ino) in &dir.children {
-                let node = self.inodes.get(&child_ino).unwrap();
-                let kind = match node {
-                    Node::File(_) => fuser::FileType::RegularFile,
-                    Node::Dir(_) => fuser::FileType::Directory,
-                };
-                entries.push((child_ino, kind, name.clone()));
-            }
-            for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
-                if reply.add(ino, (i + 1) as i64, kind, name) {
-                    break;
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            if let Some(Node::Dir(dir)) = inner.inodes.get(&ino) {
+                let mut entries = vec![(ROOT_INODE, fuser::FileType::Directory, ".".to_string()), (ROOT_INODE, fuser::FileType::Directory, "..".to_string())];
+                for (name, &child_ino) in &dir.children {
+                    let node = inner.inodes.get(&child_ino).unwrap();
+                    let kind = match node {
+                        Node::File(_) => fuser::FileType::RegularFile,
+                        Node::Dir(_) => fuser::FileType::Directory,
+                        Node::Symlink(_) => fuser::FileType::Symlink,
+                    };
+                    entries.push((child_ino, kind, name.clone()));
                 }
+                for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+                    if reply.add(ino, (i + 1) as i64, kind, name) {
+                        break;
+                    }
+                }
+                reply.ok();
+            } else {
+                reply.error(ENOENT);
             }
-            reply.ok();
-        } else {
-            reply.error(ENOENT);
-        }
+        });
     }
 
     fn mkdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, umask: u32, reply: ReplyEntry) {
-        let name_str = name.to_str().unwrap_or("");
-        // Avoid double mutable borrow by splitting logic
-        let already_exists = if let Some(Node::Dir(dir)) = self.inodes.get(&parent) {
-            dir.children.contains_key(name_str)
-        } else {
-            reply.error(ENOENT);
-            return;
-        };
-        if already_exists {
-            reply.error(libc::EEXIST);
-            return;
-        }
-        let ino = self.alloc_inode();
-        let attr = fuser::FileAttr {
-            ino,
-            size: 0,
-            blocks: 0,
-            atime: UNIX_EPOCH,
-            mtime: UNIX_EPOCH,
-            ctime: UNIX_EPOCH,
-            crtime: UNIX_EPOCH,
-            kind: fuser::FileType::Directory,
-            perm: (mode & !umask & 0o7777) as u16,
-            nlink: 2,
-            uid: unsafe { libc::geteuid() },
-            gid: unsafe { libc::getegid() },
-            rdev: 0,
-            flags: 0,
-            blksize: 512,
-        };
-        let new_dir = Node::Dir(InMemoryDir {
-            name: name_str.to_string(),
-            children: BTreeMap::new(),
-            attr,
+        let name_str = name.to_str().unwrap_or("").to_string();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            // Avoid double mutable borrow by splitting logic
+            let already_exists = if let Some(Node::Dir(dir)) = inner.inodes.get(&parent) {
+                dir.children.contains_key(&name_str)
+            } else {
+                reply.error(ENOENT);
+                return;
+            };
+            if already_exists {
+                reply.error(libc::EEXIST);
+                return;
+            }
+            let ino = inner.alloc_inode();
+            let attr = fuser::FileAttr {
+                ino,
+                size: 0,
+                blocks: 0,
+                atime: UNIX_EPOCH,
+                mtime: UNIX_EPOCH,
+                ctime: UNIX_EPOCH,
+                crtime: UNIX_EPOCH,
+                kind: fuser::FileType::Directory,
+                perm: (mode & !umask & 0o7777) as u16,
+                nlink: 2,
+                uid: unsafe { libc::geteuid() },
+                gid: unsafe { libc::getegid() },
+                rdev: 0,
+                flags: 0,
+                blksize: 512,
+            };
+            let new_dir = Node::Dir(InMemoryDir {
+                name: name_str.clone(),
+                children: BTreeMap::new(),
+                attr,
+                xattrs: BTreeMap::new(),
+            });
+            if let Some(Node::Dir(dir)) = inner.inodes.get_mut(&parent) {
+                dir.children.insert(name_str.clone(), ino);
+            }
+            inner.inodes.insert(ino, new_dir);
+            inner.track_path(parent, &name_str, ino);
+            reply.entry(&TTL, &attr, 0);
         });
-        if let Some(Node::Dir(dir)) = self.inodes.get_mut(&parent) {
-            dir.children.insert(name_str.to_string(), ino);
-        }
-        self.inodes.insert(ino, new_dir);
-        reply.entry(&TTL, &attr, 0);
     }
 
     fn create(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, flags: u32, umask: i32, reply: ReplyCreate) {
-        let name_str = name.to_str().unwrap_or("");
-        let already_exists = if let Some(Node::Dir(dir)) = self.inodes.get(&parent) {
-            dir.children.contains_key(name_str)
-        } else {
-            reply.error(ENOENT);
-            return;
-        };
-        if already_exists {
-            reply.error(libc::EEXIST);
-            return;
-        }
-        let ino = self.alloc_inode();
-        let attr = fuser::FileAttr {
-            ino,
-            size: 0,
-            blocks: 0,
-            atime: UNIX_EPOCH,
-            mtime: UNIX_EPOCH,
-            ctime: UNIX_EPOCH,
-            crtime: UNIX_EPOCH,
-            kind: fuser::FileType::RegularFile,
-            perm: (mode & !(umask as u32) & 0o7777) as u16,
-            nlink: 1,
-            uid: unsafe { libc::geteuid() },
-            gid: unsafe { libc::getegid() },
-            rdev: 0,
-            flags: 0,
-            blksize: 512,
-        };
-        let new_file = Node::File(InMemoryFile {
-            name: name_str.to_string(),
-            data: vec![],
-            attr,
+        let name_str = name.to_str().unwrap_or("").to_string();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            let already_exists = if let Some(Node::Dir(dir)) = inner.inodes.get(&parent) {
+                dir.children.contains_key(&name_str)
+            } else {
+                reply.error(ENOENT);
+                return;
+            };
+            if already_exists {
+                reply.error(libc::EEXIST);
+                return;
+            }
+            let ino = inner.alloc_inode();
+            let attr = fuser::FileAttr {
+                ino,
+                size: 0,
+                blocks: 0,
+                atime: UNIX_EPOCH,
+                mtime: UNIX_EPOCH,
+                ctime: UNIX_EPOCH,
+                crtime: UNIX_EPOCH,
+                kind: fuser::FileType::RegularFile,
+                perm: (mode & !(umask as u32) & 0o7777) as u16,
+                nlink: 1,
+                uid: unsafe { libc::geteuid() },
+                gid: unsafe { libc::getegid() },
+                rdev: 0,
+                flags: 0,
+                blksize: 512,
+            };
+            let new_file = Node::File(InMemoryFile {
+                name: name_str.clone(),
+                chunks: vec![],
+                attr,
+                xattrs: BTreeMap::new(),
+            });
+            if let Some(Node::Dir(dir)) = inner.inodes.get_mut(&parent) {
+                dir.children.insert(name_str.clone(), ino);
+            }
+            inner.inodes.insert(ino, new_file);
+            inner.track_path(parent, &name_str, ino);
+            reply.created(&TTL, &attr, 0, 0, 0);
         });
-        if let Some(Node::Dir(dir)) = self.inodes.get_mut(&parent) {
-            dir.children.insert(name_str.to_string(), ino);
-        }
-        self.inodes.insert(ino, new_file);
-        reply.created(&TTL, &attr, 0, 0, 0);
     }
 
     fn read(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, size: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyData) {
-        if let Some(Node::File(file)) = self.inodes.get(&ino) {
-            let data = &file.data;
-            let end = std::cmp::min((offset as usize) + (size as usize), data.len());
-            let start = std::cmp::min(offset as usize, data.len());
-            reply.data(&data[start..end]);
-        } else {
-            reply.error(ENOENT);
-        }
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let inner = inner.read().unwrap();
+            if let Some(Node::File(file)) = inner.inodes.get(&ino) {
+                let data = assemble_chunks(&inner.chunk_store, &file.chunks);
+                // Clip to the reported size, not the backing buffer's length:
+                // `fallocate` with FALLOC_FL_KEEP_SIZE can leave the buffer
+                // zero-padded past `attr.size`.
+                let len = file.attr.size as usize;
+                let end = std::cmp::min((offset as usize) + (size as usize), len);
+                let start = std::cmp::min(offset as usize, len);
+                reply.data(&data[start..end]);
+            } else {
+                reply.error(ENOENT);
+            }
+        });
     }
 
     fn write(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, data: &[u8], _write_flags: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyWrite) {
-        if let Some(Node::File(file)) = self.inodes.get_mut(&ino) {
-            let offset = offset as usize;
-            if file.data.len() < offset + data.len() {
-                file.data.resize(offset + data.len(), 0);
-            }
-            file.data[offset..offset + data.len()].copy_from_slice(data);
-            file.attr.size = file.data.len() as u64;
-            reply.written(data.len() as u32);
-        } else {
-            reply.error(ENOENT);
-        }
+        let data = data.to_vec();
+        let inner = Arc::clone(&self.inner);
+        self.pool.execute(move || {
+            let mut inner = inner.write().unwrap();
+            if let Some(Node::File(file)) = inner.inodes.get(&ino) {
+                let offset = offset as usize;
+                let mut bytes = assemble_chunks(&inner.chunk_store, &file.chunks);
+                if bytes.len() < offset + data.len() {
+                    bytes.resize(offset + data.len(), 0);
+                }
+                bytes[offset..offset + data.len()].copy_from_slice(&data);
+                let file = match inner.inodes.get(&ino) {
+                    Some(Node::File(file)) => file,
+                    _ => unreachable!(),
+                };
+                release_chunks(&mut inner.chunk_store, &file.chunks);
+                let new_chunks = chunk_and_intern(&mut inner.chunk_store, &bytes);
+                if let Some(Node::File(file)) = inner.inodes.get_mut(&ino) {
+                    file.chunks = new_chunks;
+                    file.attr.size = bytes.len() as u64;
+                    file.attr.blocks = blocks_for(file.attr.size);
+                }
+                reply.written(data.len() as u32);
+            } else {
+                reply.error(ENOENT);
+            }
+        });
     }
 }
 
@@ -446,7 +1251,59 @@ fn main() {
         std::process::exit(0);
     }).expect("Error setting Ctrl+C handler");
 
-    let fs = MemFS::new();
-    info!("Mounting MemFS at {}", mountpoint);
-    fuser::mount2(fs, mountpoint, &[MountOption::FSName("memfs".to_string()), MountOption::AutoUnmount]).unwrap();
+    let args: Vec<String> = std::env::args().collect();
+
+    // `--capacity <bytes>` overrides the space `statfs` reports the mount as having.
+    let capacity_bytes = args.iter().position(|a| a == "--capacity")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_CAPACITY_BYTES);
+
+    // `--provider <memory|sqlite>` switches the mount from the legacy, built-in
+    // `MemFS` to the `Provider`/`FuseFS` abstraction in `providers/`; `sqlite`
+    // additionally requires `--db <path>` naming the backing database file.
+    let provider_name = args.iter().position(|a| a == "--provider")
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str());
+
+    match provider_name {
+        Some("memory") => {
+            let mut provider = providers::memory::MemoryProvider::new_with_mode_and_persist(false, store_path(&args));
+            provider.capacity_bytes = capacity_bytes;
+            let fs = fusefs::FuseFS::new(Box::new(provider));
+            info!("Mounting FuseFS (memory provider) at {}", mountpoint);
+            fuser::mount2(fs, mountpoint, &[MountOption::FSName("memfs".to_string()), MountOption::AutoUnmount]).unwrap();
+        }
+        Some("sqlite") => {
+            let db_path = args.iter().position(|a| a == "--db")
+                .and_then(|i| args.get(i + 1))
+                .expect("--provider sqlite requires --db <path>");
+            let mut provider = providers::sqlite_simple::SqliteProvider::new(db_path)
+                .expect("failed to open sqlite provider database");
+            provider.capacity_bytes = capacity_bytes;
+            let fs = fusefs::FuseFS::new(Box::new(provider));
+            info!("Mounting FuseFS (sqlite provider) at {}", mountpoint);
+            fuser::mount2(fs, mountpoint, &[MountOption::FSName("memfs".to_string()), MountOption::AutoUnmount]).unwrap();
+        }
+        Some(other) => {
+            panic!("unknown --provider {:?}, expected \"memory\" or \"sqlite\"", other);
+        }
+        None => {
+            // `--persist <dir>` switches from scratch in-memory storage to a durable
+            // content-addressed store that survives unmount/remount.
+            let store = store_path(&args).map(|dir| Store::open(dir).expect("failed to open persistent store"));
+            let fs = MemFS::new_with_store_and_capacity(store, capacity_bytes);
+            info!("Mounting MemFS at {}", mountpoint);
+            fuser::mount2(fs, mountpoint, &[MountOption::FSName("memfs".to_string()), MountOption::AutoUnmount]).unwrap();
+        }
+    }
+}
+
+/// Shared `--persist <path>` parsing. Under `--provider memory` the path names
+/// the single snapshot file `MemoryProvider::load`/`save` read and write; for
+/// the legacy `MemFS` it names the directory `Store::open` keeps its store in.
+fn store_path(args: &[String]) -> Option<PathBuf> {
+    args.iter().position(|a| a == "--persist")
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from)
 }
@@ -1,36 +1,222 @@
 use std::collections::{HashMap, BTreeMap};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::ffi::OsStr;
 use std::time::SystemTime;
+use std::io::Write as _;
 use fuser;
+use serde::{Serialize, Deserialize};
 use crate::providers::Provider;
 
 const ROOT_INODE: u64 = 1;
 const USER_INODE_START: u64 = 10;
+const STATFS_BLOCK_SIZE: u32 = 512;
+/// Capacity ceiling `statfs` reports the tree as having (1 TiB).
+const DEFAULT_CAPACITY_BYTES: u64 = 1024 * 1024 * 1024 * 1024;
 
-#[derive(Debug, Clone)]
+/// Content-defined chunking window for the `ChunkStore`: boundaries are cut
+/// on a rolling-hash condition but never
+/// let a chunk shrink below `CDC_MIN_SIZE` or grow past `CDC_MAX_SIZE`.
+const CDC_MIN_SIZE: usize = 2 * 1024;
+const CDC_MAX_SIZE: usize = 64 * 1024;
+/// Rolling-hash window width used to decide chunk boundaries.
+const CDC_WINDOW: usize = 48;
+/// Cut whenever the low `CDC_MASK_BITS` bits of the rolling hash are zero,
+/// which targets an ~8 KiB average chunk size.
+const CDC_MASK_BITS: u32 = 13;
+
+/// Splits `data` into content-defined chunks so that inserting or deleting
+/// bytes in the middle of a file only perturbs the chunks touching the edit,
+/// letting unrelated chunks (and therefore their `ChunkStore` entries) stay
+/// shared across files and across edits of the same file.
+fn cdc_split(data: &[u8]) -> Vec<&[u8]> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let mut boundaries = Vec::new();
+    let mask = (1u64 << CDC_MASK_BITS) - 1;
+    let mut start = 0usize;
+    let mut hash: u64 = 0;
+    for i in 0..data.len() {
+        hash = hash.rotate_left(1) ^ (data[i] as u64);
+        let len = i - start + 1;
+        if len > CDC_WINDOW {
+            hash ^= (data[i - CDC_WINDOW] as u64).rotate_left(CDC_WINDOW as u32);
+        }
+        if len >= CDC_WINDOW && len >= CDC_MIN_SIZE && (hash & mask) == 0 {
+            boundaries.push(i + 1);
+            start = i + 1;
+            hash = 0;
+        } else if len >= CDC_MAX_SIZE {
+            boundaries.push(i + 1);
+            start = i + 1;
+            hash = 0;
+        }
+    }
+    if start < data.len() {
+        boundaries.push(data.len());
+    }
+    let mut slices = Vec::with_capacity(boundaries.len());
+    let mut prev = 0usize;
+    for end in boundaries {
+        slices.push(&data[prev..end]);
+        prev = end;
+    }
+    slices
+}
+
+/// Checks `req_uid`/`req_gid` against `attr`'s owner/group/other rwx bits,
+/// the standard POSIX rule the kernel would otherwise enforce itself when
+/// the mount sets `default_permissions`. `mask` uses the `libc::{R,W,X}_OK`
+/// bits from `access(2)`.
+fn check_access(attr: &fuser::FileAttr, req_uid: u32, req_gid: u32, mask: i32) -> bool {
+    if mask == libc::F_OK {
+        return true;
+    }
+    if req_uid == 0 {
+        return true;
+    }
+    let bits = if req_uid == attr.uid {
+        (attr.perm >> 6) & 0o7
+    } else if req_gid == attr.gid {
+        (attr.perm >> 3) & 0o7
+    } else {
+        attr.perm & 0o7
+    } as i32;
+    (bits & mask) == mask
+}
+
+/// Content-addressed store backing every `InMemoryFile`'s `chunks` list: a
+/// blake3 hash of a chunk's bytes maps to the bytes plus a refcount, so
+/// identical content written by unrelated files (or unrelated writes to the
+/// same file) is stored exactly once.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkStore {
+    chunks: HashMap<[u8; 32], (Vec<u8>, u32)>,
+}
+
+impl ChunkStore {
+    fn new() -> Self {
+        Self { chunks: HashMap::new() }
+    }
+
+    /// Interns `data` as a chunk, bumping its refcount if already present,
+    /// and returns its content hash.
+    fn intern(&mut self, data: &[u8]) -> [u8; 32] {
+        let hash = *blake3::hash(data).as_bytes();
+        self.chunks
+            .entry(hash)
+            .and_modify(|(_, refcount)| *refcount += 1)
+            .or_insert_with(|| (data.to_vec(), 1));
+        hash
+    }
+
+    /// Drops one reference to `hash`, freeing its bytes once the refcount
+    /// reaches zero.
+    fn release(&mut self, hash: &[u8; 32]) {
+        if let Some((_, refcount)) = self.chunks.get_mut(hash) {
+            *refcount -= 1;
+            if *refcount == 0 {
+                self.chunks.remove(hash);
+            }
+        }
+    }
+
+    fn get(&self, hash: &[u8; 32]) -> &[u8] {
+        self.chunks.get(hash).map(|(bytes, _)| bytes.as_slice()).unwrap_or(&[])
+    }
+
+    /// Total bytes actually resident in the store, i.e. the deduplicated
+    /// size `statfs` can report instead of the sum of every file's nominal
+    /// size.
+    fn resident_bytes(&self) -> u64 {
+        self.chunks.values().map(|(bytes, _)| bytes.len() as u64).sum()
+    }
+}
+
+/// Mirrors every field of `fuser::FileType` so `Node` and friends can derive
+/// `Serialize`/`Deserialize` even though the upstream type doesn't.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "fuser::FileType")]
+enum FileTypeDef {
+    NamedPipe,
+    CharDevice,
+    BlockDevice,
+    Directory,
+    RegularFile,
+    Symlink,
+    Socket,
+}
+
+/// Mirrors every field of `fuser::FileAttr` so `Node` and friends can derive
+/// `Serialize`/`Deserialize` even though the upstream type doesn't.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "fuser::FileAttr")]
+struct FileAttrDef {
+    ino: u64,
+    size: u64,
+    blocks: u64,
+    atime: SystemTime,
+    mtime: SystemTime,
+    ctime: SystemTime,
+    crtime: SystemTime,
+    #[serde(with = "FileTypeDef")]
+    kind: fuser::FileType,
+    perm: u16,
+    nlink: u32,
+    uid: u32,
+    gid: u32,
+    rdev: u32,
+    flags: u32,
+    blksize: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InMemoryFile {
-    pub data: Vec<u8>,
+    /// Content hashes of this file's chunks, in order; the bytes themselves
+    /// live in `MemoryProvider::chunk_store` so identical content is shared.
+    pub chunks: Vec<[u8; 32]>,
+    pub size: u64,
+    #[serde(with = "FileAttrDef")]
     pub attr: fuser::FileAttr,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InMemoryDir {
     pub children: BTreeMap<String, u64>,
+    #[serde(with = "FileAttrDef")]
     pub attr: fuser::FileAttr,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InMemorySymlink {
     pub target: String,
+    #[serde(with = "FileAttrDef")]
     pub attr: fuser::FileAttr,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Node {
     File(InMemoryFile),
     Dir(InMemoryDir),
     Symlink(InMemorySymlink),
+    Device {
+        rdev: u32,
+        #[serde(with = "FileAttrDef")]
+        attr: fuser::FileAttr,
+    },
+    Fifo(#[serde(with = "FileAttrDef")] fuser::FileAttr),
+    Socket(#[serde(with = "FileAttrDef")] fuser::FileAttr),
+}
+
+/// On-disk layout written by `MemoryProvider::save` and read back by `load`:
+/// the whole inode table plus the bits needed to resume allocating inodes
+/// and serving xattrs, zstd-compressed so large trees stay small on disk.
+#[derive(Serialize, Deserialize)]
+struct PersistedState {
+    next_inode: u64,
+    inodes: HashMap<u64, Node>,
+    xattrs: HashMap<(u64, String), Vec<u8>>,
+    chunk_store: ChunkStore,
 }
 
 pub struct MemoryProvider {
@@ -38,9 +224,18 @@ pub struct MemoryProvider {
     #[allow(dead_code)]
     pub paths: HashMap<PathBuf, u64>,
     pub next_inode: u64,
-    #[allow(dead_code)]
     pub xattrs: HashMap<(u64, String), Vec<u8>>,
     pub osx_mode: bool,
+    /// Set by `--persist <path>`; when present, `destroy` flushes the tree here.
+    pub persist_path: Option<PathBuf>,
+    /// Ceiling `statfs` reports the tree as having; see `DEFAULT_CAPACITY_BYTES`.
+    pub capacity_bytes: u64,
+    /// Content-addressed backing store shared by every `InMemoryFile`.
+    pub chunk_store: ChunkStore,
+    /// Outstanding kernel lookup references per inode, balanced by `forget`;
+    /// a non-directory inode is only reclaimed once this drops to zero *and*
+    /// `nlink == 0`; `rmdir` drops a directory at once.
+    pub lookup_counts: HashMap<u64, u64>,
 }
 
 impl MemoryProvider {
@@ -48,7 +243,35 @@ impl MemoryProvider {
     pub fn new() -> Self {
         Self::new_with_mode(false)
     }
+    fn node_attr(&self, ino: u64) -> Option<fuser::FileAttr> {
+        self.inodes.get(&ino).map(|node| match node {
+            Node::File(f) => f.attr,
+            Node::Dir(d) => d.attr,
+            Node::Symlink(s) => s.attr,
+            Node::Device { attr, .. } => *attr,
+            Node::Fifo(attr) | Node::Socket(attr) => *attr,
+        })
+    }
     pub fn new_with_mode(osx_mode: bool) -> Self {
+        Self::new_with_mode_and_persist(osx_mode, None)
+    }
+    /// Like `new_with_mode`, but if `persist_path` names an existing snapshot
+    /// written by `save`, the tree is restored from it instead of starting empty.
+    pub fn new_with_mode_and_persist(osx_mode: bool, persist_path: Option<PathBuf>) -> Self {
+        if let Some(path) = &persist_path {
+            if path.exists() {
+                match Self::load(path) {
+                    Ok(mut restored) => {
+                        restored.osx_mode = osx_mode;
+                        restored.persist_path = persist_path;
+                        return restored;
+                    }
+                    Err(e) => {
+                        log::warn!("failed to load persisted state from {}: {}, starting fresh", path.display(), e);
+                    }
+                }
+            }
+        }
         let mut inodes = HashMap::new();
         let mut paths = HashMap::new();
         let now = SystemTime::now();
@@ -75,13 +298,104 @@ impl MemoryProvider {
         });
         inodes.insert(ROOT_INODE, root);
         paths.insert(PathBuf::from("/"), ROOT_INODE);
-        Self { inodes, paths, next_inode: USER_INODE_START, xattrs: HashMap::new(), osx_mode }
+        Self { inodes, paths, next_inode: USER_INODE_START, xattrs: HashMap::new(), osx_mode, persist_path, capacity_bytes: DEFAULT_CAPACITY_BYTES, chunk_store: ChunkStore::new(), lookup_counts: HashMap::new() }
     }
     pub fn alloc_inode(&mut self) -> u64 {
         let ino = self.next_inode;
         self.next_inode += 1;
         ino
     }
+
+    /// Deduplicated size of the tree: the `ChunkStore`'s resident bytes,
+    /// which is at most (and usually well under) the sum of every file's
+    /// nominal `size`.
+    fn used_bytes(&self) -> u64 {
+        self.chunk_store.resident_bytes()
+    }
+
+    /// Re-chunks `data` with `cdc_split`, interning each piece in
+    /// `chunk_store` and returning the resulting hash list. Used by both
+    /// `write` (on the reconstructed whole file) and `setattr` truncation.
+    fn chunk_and_intern(&mut self, data: &[u8]) -> Vec<[u8; 32]> {
+        cdc_split(data).into_iter().map(|chunk| self.chunk_store.intern(chunk)).collect()
+    }
+
+    /// Releases every chunk in `hashes` from `chunk_store`.
+    fn release_chunks(&mut self, hashes: &[[u8; 32]]) {
+        for hash in hashes {
+            self.chunk_store.release(hash);
+        }
+    }
+
+    /// Reclaims `ino` once it has no directory entries left (`nlink == 0`)
+    /// and no outstanding kernel lookups, releasing its chunks and xattrs.
+    fn try_reap(&mut self, ino: u64) {
+        let nlink = match self.inodes.get(&ino) {
+            Some(Node::File(f)) => f.attr.nlink,
+            Some(Node::Symlink(s)) => s.attr.nlink,
+            Some(Node::Device { attr, .. }) => attr.nlink,
+            Some(Node::Fifo(attr)) | Some(Node::Socket(attr)) => attr.nlink,
+            Some(Node::Dir(_)) | None => return,
+        };
+        let lookups = self.lookup_counts.get(&ino).copied().unwrap_or(0);
+        if nlink == 0 && lookups == 0 {
+            if let Some(Node::File(file)) = self.inodes.remove(&ino) {
+                self.release_chunks(&file.chunks);
+            }
+            self.xattrs.retain(|(xino, _), _| *xino != ino);
+            self.lookup_counts.remove(&ino);
+        }
+    }
+
+    /// Reassembles a file's full contents by walking its chunk list.
+    fn reconstruct(&self, chunks: &[[u8; 32]]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for hash in chunks {
+            buf.extend_from_slice(self.chunk_store.get(hash));
+        }
+        buf
+    }
+
+    /// `true` once growing a file by `additional_bytes` would exceed `capacity_bytes`.
+    fn would_exceed_capacity(&self, additional_bytes: u64) -> bool {
+        self.used_bytes() + additional_bytes > self.capacity_bytes
+    }
+
+    /// Serializes the whole tree to `path` as a zstd-compressed bincode blob.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let state = PersistedState {
+            next_inode: self.next_inode,
+            inodes: self.inodes.clone(),
+            xattrs: self.xattrs.clone(),
+            chunk_store: self.chunk_store.clone(),
+        };
+        let bytes = bincode::serialize(&state)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let file = std::fs::File::create(path)?;
+        let mut encoder = zstd::stream::Encoder::new(file, 0)?;
+        encoder.write_all(&bytes)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Reads back a snapshot written by `save`.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let decoder = zstd::stream::Decoder::new(file)?;
+        let state: PersistedState = bincode::deserialize_from(decoder)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(Self {
+            inodes: state.inodes,
+            paths: HashMap::new(),
+            next_inode: state.next_inode,
+            xattrs: state.xattrs,
+            osx_mode: false,
+            persist_path: None,
+            capacity_bytes: DEFAULT_CAPACITY_BYTES,
+            chunk_store: state.chunk_store,
+            lookup_counts: HashMap::new(),
+        })
+    }
 }
 
 impl Provider for MemoryProvider {
@@ -114,13 +428,14 @@ impl Provider for MemoryProvider {
             parent_dir.children.remove(name_str);
         }
         self.inodes.remove(&ino);
+        self.xattrs.retain(|(xino, _), _| *xino != ino);
         reply.ok();
     }
-    fn open(&mut self, ino: u64, reply: fuser::ReplyOpen) {
-        if self.inodes.contains_key(&ino) {
-            reply.opened(0, 0);
-        } else {
-            reply.error(libc::ENOENT);
+    fn open(&mut self, req_uid: u32, req_gid: u32, ino: u64, reply: fuser::ReplyOpen) {
+        match self.node_attr(ino) {
+            Some(attr) if check_access(&attr, req_uid, req_gid, libc::R_OK) => reply.opened(0, 0),
+            Some(_) => reply.error(libc::EACCES),
+            None => reply.error(libc::ENOENT),
         }
     }
     fn flush(&mut self, ino: u64, reply: fuser::ReplyEmpty) {
@@ -144,13 +459,34 @@ impl Provider for MemoryProvider {
                 fuser::TimeOrNow::Now => SystemTime::now(),
             }
         }
+        if let Some(new_size) = size {
+            let current_len = match self.inodes.get(&ino) {
+                Some(Node::File(f)) => f.size,
+                _ => 0,
+            };
+            let growth = new_size.saturating_sub(current_len);
+            if growth > 0 && self.would_exceed_capacity(growth) {
+                reply.error(libc::ENOSPC);
+                return;
+            }
+        }
+        if let Some(new_size) = size {
+            if let Some(Node::File(f)) = self.inodes.get(&ino) {
+                let mut data = self.reconstruct(&f.chunks);
+                data.resize(new_size as usize, 0);
+                let old_chunks = f.chunks.clone();
+                let new_chunks = self.chunk_and_intern(&data);
+                self.release_chunks(&old_chunks);
+                if let Some(Node::File(f)) = self.inodes.get_mut(&ino) {
+                    f.chunks = new_chunks;
+                    f.size = new_size;
+                    f.attr.size = new_size;
+                }
+            }
+        }
         if let Some(node) = self.inodes.get_mut(&ino) {
             match node {
                 Node::File(f) => {
-                    if let Some(new_size) = size {
-                        f.data.resize(new_size as usize, 0);
-                        f.attr.size = new_size;
-                    }
                     if let Some(m) = mode { f.attr.perm = m as u16; }
                     if let Some(u) = uid { f.attr.uid = u; }
                     if let Some(g) = gid { f.attr.gid = g; }
@@ -183,6 +519,17 @@ impl Provider for MemoryProvider {
                     if let Some(fg) = flags { s.attr.flags = fg; }
                     reply.attr(&std::time::Duration::from_secs(1), &s.attr);
                 }
+                Node::Device { attr, .. } | Node::Fifo(attr) | Node::Socket(attr) => {
+                    if let Some(m) = mode { attr.perm = m as u16; }
+                    if let Some(u) = uid { attr.uid = u; }
+                    if let Some(g) = gid { attr.gid = g; }
+                    if let Some(a) = atime { attr.atime = timeornow_to_systemtime(a); }
+                    if let Some(m) = mtime { attr.mtime = timeornow_to_systemtime(m); }
+                    if let Some(c) = ctime { attr.ctime = c; }
+                    if let Some(cr) = crtime { attr.crtime = cr; }
+                    if let Some(fg) = flags { attr.flags = fg; }
+                    reply.attr(&std::time::Duration::from_secs(1), attr);
+                }
             }
         } else {
             reply.error(libc::ENOENT);
@@ -198,7 +545,10 @@ impl Provider for MemoryProvider {
                         Node::File(f) => f.attr,
                         Node::Dir(d) => d.attr,
                         Node::Symlink(s) => s.attr,
+                        Node::Device { attr, .. } => *attr,
+                        Node::Fifo(attr) | Node::Socket(attr) => *attr,
                     };
+                    *self.lookup_counts.entry(child_ino).or_insert(0) += 1;
                     reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
                     return;
                 }
@@ -212,6 +562,8 @@ impl Provider for MemoryProvider {
                 Node::File(f) => f.attr,
                 Node::Dir(d) => d.attr,
                 Node::Symlink(s) => s.attr,
+                Node::Device { attr, .. } => *attr,
+                Node::Fifo(attr) | Node::Socket(attr) => *attr,
             };
             reply.attr(&std::time::Duration::from_secs(1), &attr);
         } else {
@@ -230,6 +582,9 @@ impl Provider for MemoryProvider {
                     Node::File(_) => fuser::FileType::RegularFile,
                     Node::Dir(_) => fuser::FileType::Directory,
                     Node::Symlink(_) => fuser::FileType::Symlink,
+                    Node::Device { attr, .. } => attr.kind,
+                    Node::Fifo(_) => fuser::FileType::NamedPipe,
+                    Node::Socket(_) => fuser::FileType::Socket,
                 };
                 entries.push((child_ino, kind, name.clone()));
             }
@@ -286,6 +641,7 @@ impl Provider for MemoryProvider {
             dir.children.insert(name_str.to_string(), ino);
         }
         self.inodes.insert(ino, new_dir);
+        *self.lookup_counts.entry(ino).or_insert(0) += 1;
         reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
     }
     fn create(&mut self, parent: u64, name: &OsStr, mode: u32, _flags: u32, umask: i32, reply: fuser::ReplyCreate) {
@@ -324,18 +680,28 @@ impl Provider for MemoryProvider {
             blksize: 512,
         };
         let new_file = Node::File(InMemoryFile {
-            data: vec![],
+            chunks: vec![],
+            size: 0,
             attr,
         });
         if let Some(Node::Dir(dir)) = self.inodes.get_mut(&parent) {
             dir.children.insert(name_str.to_string(), ino);
         }
         self.inodes.insert(ino, new_file);
+        *self.lookup_counts.entry(ino).or_insert(0) += 1;
         reply.created(&std::time::Duration::from_secs(1), &attr, 0, 0, 0);
     }
-    fn read(&mut self, ino: u64, offset: i64, size: u32, reply: fuser::ReplyData) {
+    fn read(&mut self, req_uid: u32, req_gid: u32, ino: u64, offset: i64, size: u32, reply: fuser::ReplyData) {
+        let Some(attr) = self.node_attr(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        if !check_access(&attr, req_uid, req_gid, libc::R_OK) {
+            reply.error(libc::EACCES);
+            return;
+        }
         if let Some(Node::File(file)) = self.inodes.get(&ino) {
-            let data = &file.data;
+            let data = self.reconstruct(&file.chunks);
             let end = std::cmp::min((offset as usize) + (size as usize), data.len());
             let start = std::cmp::min(offset as usize, data.len());
             reply.data(&data[start..end]);
@@ -343,14 +709,42 @@ impl Provider for MemoryProvider {
             reply.error(libc::ENOENT);
         }
     }
-    fn write(&mut self, ino: u64, offset: i64, data: &[u8], reply: fuser::ReplyWrite) {
+    fn write(&mut self, req_uid: u32, req_gid: u32, ino: u64, offset: i64, data: &[u8], reply: fuser::ReplyWrite) {
+        match self.node_attr(ino) {
+            Some(attr) if check_access(&attr, req_uid, req_gid, libc::W_OK) => {}
+            Some(_) => { reply.error(libc::EACCES); return; }
+            None => { reply.error(libc::ENOENT); return; }
+        }
+        let offset = offset as usize;
+        let current_len = match self.inodes.get(&ino) {
+            Some(Node::File(file)) => file.size as usize,
+            Some(_) => { reply.error(libc::ENOENT); return; }
+            None => { reply.error(libc::ENOENT); return; }
+        };
+        let growth = (offset + data.len()).saturating_sub(current_len) as u64;
+        if growth > 0 && self.would_exceed_capacity(growth) {
+            reply.error(libc::ENOSPC);
+            return;
+        }
+        let old_chunks = match self.inodes.get(&ino) {
+            Some(Node::File(file)) => file.chunks.clone(),
+            _ => { reply.error(libc::ENOENT); return; }
+        };
+        // Reconstruct the whole file, splice in the new bytes, then re-chunk
+        // from scratch; unaffected regions mostly fall back on the same
+        // content-defined boundaries and so reuse their existing chunks.
+        let mut buf = self.reconstruct(&old_chunks);
+        if buf.len() < offset + data.len() {
+            buf.resize(offset + data.len(), 0);
+        }
+        buf[offset..offset + data.len()].copy_from_slice(data);
+        let new_size = buf.len() as u64;
+        let new_chunks = self.chunk_and_intern(&buf);
+        self.release_chunks(&old_chunks);
         if let Some(Node::File(file)) = self.inodes.get_mut(&ino) {
-            let offset = offset as usize;
-            if file.data.len() < offset + data.len() {
-                file.data.resize(offset + data.len(), 0);
-            }
-            file.data[offset..offset + data.len()].copy_from_slice(data);
-            file.attr.size = file.data.len() as u64;
+            file.chunks = new_chunks;
+            file.size = new_size;
+            file.attr.size = new_size;
             reply.written(data.len() as u32);
         } else {
             reply.error(libc::ENOENT);
@@ -371,21 +765,28 @@ impl Provider for MemoryProvider {
                 return;
             }
         };
-        match self.inodes.get(&ino) {
-            Some(Node::File(_)) | Some(Node::Symlink(_)) => {
-                if let Some(Node::Dir(parent_dir)) = self.inodes.get_mut(&parent) {
-                    parent_dir.children.remove(name_str);
-                }
-                self.inodes.remove(&ino);
-                reply.ok();
-            }
+        match self.inodes.get_mut(&ino) {
+            Some(Node::File(f)) => { f.attr.nlink = f.attr.nlink.saturating_sub(1); }
+            Some(Node::Symlink(s)) => { s.attr.nlink = s.attr.nlink.saturating_sub(1); }
+            Some(Node::Device { attr, .. }) => { attr.nlink = attr.nlink.saturating_sub(1); }
+            Some(Node::Fifo(attr)) | Some(Node::Socket(attr)) => { attr.nlink = attr.nlink.saturating_sub(1); }
             Some(Node::Dir(_)) => {
                 reply.error(libc::EISDIR);
+                return;
             }
             None => {
                 reply.error(libc::ENOENT);
+                return;
             }
         }
+        if let Some(Node::Dir(parent_dir)) = self.inodes.get_mut(&parent) {
+            parent_dir.children.remove(name_str);
+        }
+        // The `Node` itself (and its chunks/xattrs) is only freed once
+        // `nlink` and the kernel's lookup count both reach zero, so a
+        // process with the file open keeps reading it until it closes.
+        self.try_reap(ino);
+        reply.ok();
     }
     fn rename(&mut self, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, _flags: u32, reply: fuser::ReplyEmpty) {
         let name_str = name.to_str().unwrap_or("");
@@ -470,6 +871,7 @@ impl Provider for MemoryProvider {
             dir.children.insert(name_str.to_string(), ino);
         }
         self.inodes.insert(ino, symlink);
+        *self.lookup_counts.entry(ino).or_insert(0) += 1;
         reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
     }
     fn readlink(&mut self, ino: u64, reply: fuser::ReplyData) {
@@ -479,4 +881,201 @@ impl Provider for MemoryProvider {
             reply.error(libc::EINVAL);
         }
     }
+    fn destroy(&mut self) {
+        if let Some(path) = self.persist_path.clone() {
+            if let Err(e) = self.save(&path) {
+                log::warn!("failed to persist MemoryProvider state to {}: {}", path.display(), e);
+            }
+        }
+    }
+    fn statfs(&mut self, _ino: u64, reply: fuser::ReplyStatfs) {
+        let used_bytes = self.used_bytes();
+        let total_blocks = self.capacity_bytes / STATFS_BLOCK_SIZE as u64;
+        let used_blocks = (used_bytes + STATFS_BLOCK_SIZE as u64 - 1) / STATFS_BLOCK_SIZE as u64;
+        let free_blocks = total_blocks.saturating_sub(used_blocks);
+        let files = self.inodes.len() as u64;
+        const FFREE_SENTINEL: u64 = 1_000_000;
+        reply.statfs(
+            total_blocks,
+            free_blocks,
+            free_blocks,
+            files,
+            FFREE_SENTINEL,
+            STATFS_BLOCK_SIZE,
+            255,
+            STATFS_BLOCK_SIZE,
+        );
+    }
+    fn setxattr(&mut self, ino: u64, name: &OsStr, value: &[u8], flags: i32, reply: fuser::ReplyEmpty) {
+        if !self.inodes.contains_key(&ino) {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name = name.to_str().unwrap_or("").to_string();
+        let key = (ino, name);
+        let exists = self.xattrs.contains_key(&key);
+        if flags & libc::XATTR_CREATE != 0 && exists {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        if flags & libc::XATTR_REPLACE != 0 && !exists {
+            reply.error(libc::ENODATA);
+            return;
+        }
+        self.xattrs.insert(key, value.to_vec());
+        reply.ok();
+    }
+    fn getxattr(&mut self, ino: u64, name: &OsStr, size: u32, reply: fuser::ReplyXattr) {
+        if !self.inodes.contains_key(&ino) {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name = name.to_str().unwrap_or("");
+        match self.xattrs.get(&(ino, name.to_string())) {
+            Some(value) => {
+                if size == 0 {
+                    reply.size(value.len() as u32);
+                } else if value.len() > size as usize {
+                    reply.error(libc::ERANGE);
+                } else {
+                    reply.data(value);
+                }
+            }
+            None => reply.error(libc::ENODATA),
+        }
+    }
+    fn listxattr(&mut self, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        if !self.inodes.contains_key(&ino) {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let mut names = Vec::new();
+        for (key, _) in self.xattrs.iter() {
+            if key.0 == ino {
+                names.extend_from_slice(key.1.as_bytes());
+                names.push(0);
+            }
+        }
+        if size == 0 {
+            reply.size(names.len() as u32);
+        } else if names.len() > size as usize {
+            reply.error(libc::ERANGE);
+        } else {
+            reply.data(&names);
+        }
+    }
+    fn removexattr(&mut self, ino: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        if !self.inodes.contains_key(&ino) {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name = name.to_str().unwrap_or("").to_string();
+        if self.xattrs.remove(&(ino, name)).is_some() {
+            reply.ok();
+        } else {
+            reply.error(libc::ENODATA);
+        }
+    }
+    fn mknod(&mut self, parent: u64, name: &OsStr, mode: u32, umask: u32, rdev: u32, reply: fuser::ReplyEntry) {
+        let name_str = name.to_str().unwrap_or("");
+        if self.osx_mode && name_str.starts_with("._") {
+            reply.error(libc::EACCES);
+            return;
+        }
+        let already_exists = if let Some(Node::Dir(dir)) = self.inodes.get(&parent) {
+            dir.children.contains_key(name_str)
+        } else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        if already_exists {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        let kind = match mode & libc::S_IFMT {
+            libc::S_IFBLK => fuser::FileType::BlockDevice,
+            libc::S_IFCHR => fuser::FileType::CharDevice,
+            libc::S_IFIFO => fuser::FileType::NamedPipe,
+            libc::S_IFSOCK => fuser::FileType::Socket,
+            _ => fuser::FileType::RegularFile,
+        };
+        let ino = self.alloc_inode();
+        let now = SystemTime::now();
+        let attr = fuser::FileAttr {
+            ino,
+            size: 0,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            crtime: now,
+            kind,
+            perm: (mode & !umask & 0o7777) as u16,
+            nlink: 1,
+            uid: unsafe { libc::geteuid() },
+            gid: unsafe { libc::getegid() },
+            rdev,
+            flags: 0,
+            blksize: 512,
+        };
+        let node = match kind {
+            fuser::FileType::BlockDevice | fuser::FileType::CharDevice => Node::Device { rdev, attr },
+            fuser::FileType::NamedPipe => Node::Fifo(attr),
+            fuser::FileType::Socket => Node::Socket(attr),
+            _ => Node::File(InMemoryFile { chunks: vec![], size: 0, attr }),
+        };
+        if let Some(Node::Dir(dir)) = self.inodes.get_mut(&parent) {
+            dir.children.insert(name_str.to_string(), ino);
+        }
+        self.inodes.insert(ino, node);
+        *self.lookup_counts.entry(ino).or_insert(0) += 1;
+        reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
+    }
+    fn link(&mut self, ino: u64, newparent: u64, newname: &OsStr, reply: fuser::ReplyEntry) {
+        let name_str = newname.to_str().unwrap_or("");
+        let already_exists = match self.inodes.get(&newparent) {
+            Some(Node::Dir(dir)) => dir.children.contains_key(name_str),
+            _ => {
+                reply.error(libc::ENOTDIR);
+                return;
+            }
+        };
+        if already_exists {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        let attr = match self.inodes.get_mut(&ino) {
+            Some(Node::File(f)) => { f.attr.nlink += 1; f.attr }
+            Some(Node::Symlink(s)) => { s.attr.nlink += 1; s.attr }
+            Some(Node::Device { attr, .. }) => { attr.nlink += 1; *attr }
+            Some(Node::Fifo(attr)) | Some(Node::Socket(attr)) => { attr.nlink += 1; *attr }
+            Some(Node::Dir(_)) => {
+                reply.error(libc::EPERM);
+                return;
+            }
+            None => {
+                reply.error(libc::ENOENT);
+                return;
+            }
+        };
+        if let Some(Node::Dir(dir)) = self.inodes.get_mut(&newparent) {
+            dir.children.insert(name_str.to_string(), ino);
+        }
+        *self.lookup_counts.entry(ino).or_insert(0) += 1;
+        reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
+    }
+    fn forget(&mut self, ino: u64, nlookup: u64) {
+        let remaining = self.lookup_counts.entry(ino).or_insert(0);
+        *remaining = remaining.saturating_sub(nlookup);
+        if *remaining == 0 {
+            self.try_reap(ino);
+        }
+    }
+    fn access(&mut self, req_uid: u32, req_gid: u32, ino: u64, mask: i32, reply: fuser::ReplyEmpty) {
+        match self.node_attr(ino) {
+            Some(attr) if check_access(&attr, req_uid, req_gid, mask) => reply.ok(),
+            Some(_) => reply.error(libc::EACCES),
+            None => reply.error(libc::ENOENT),
+        }
+    }
 } 
\ No newline at end of file
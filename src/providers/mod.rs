@@ -1,11 +1,12 @@
 pub mod memory;
+pub mod sqlite_simple;
 
-use fuser::{Request, ReplyAttr, ReplyEntry, ReplyDirectory, ReplyData, ReplyCreate, ReplyWrite};
+use fuser::{Request, ReplyAttr, ReplyEntry, ReplyDirectory, ReplyData, ReplyCreate, ReplyWrite, ReplyStatfs, ReplyXattr};
 use std::ffi::OsStr;
 
 pub trait Provider {
     fn rmdir(&mut self, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty);
-    fn open(&mut self, ino: u64, reply: fuser::ReplyOpen);
+    fn open(&mut self, req_uid: u32, req_gid: u32, ino: u64, reply: fuser::ReplyOpen);
     fn flush(&mut self, ino: u64, reply: fuser::ReplyEmpty);
     fn release(&mut self, ino: u64, reply: fuser::ReplyEmpty);
     fn setattr(&mut self, ino: u64, mode: Option<u32>, uid: Option<u32>, gid: Option<u32>, size: Option<u64>, atime: Option<fuser::TimeOrNow>, mtime: Option<fuser::TimeOrNow>, ctime: Option<std::time::SystemTime>, crtime: Option<std::time::SystemTime>, flags: Option<u32>, reply: ReplyAttr);
@@ -14,6 +15,32 @@ pub trait Provider {
     fn readdir(&mut self, ino: u64, offset: i64, reply: ReplyDirectory);
     fn mkdir(&mut self, parent: u64, name: &OsStr, mode: u32, umask: u32, reply: ReplyEntry);
     fn create(&mut self, parent: u64, name: &OsStr, mode: u32, flags: u32, umask: i32, reply: ReplyCreate);
-    fn read(&mut self, ino: u64, offset: i64, size: u32, reply: ReplyData);
-    fn write(&mut self, ino: u64, offset: i64, data: &[u8], reply: ReplyWrite);
-} 
\ No newline at end of file
+    fn read(&mut self, req_uid: u32, req_gid: u32, ino: u64, offset: i64, size: u32, reply: ReplyData);
+    fn write(&mut self, req_uid: u32, req_gid: u32, ino: u64, offset: i64, data: &[u8], reply: ReplyWrite);
+    /// Checks `req_uid`/`req_gid` against the owner/group/other rwx bits in
+    /// the stored `perm`, the same standard POSIX check the kernel would
+    /// otherwise skip if the mount is used with `default_permissions` off.
+    fn access(&mut self, req_uid: u32, req_gid: u32, ino: u64, mask: i32, reply: fuser::ReplyEmpty);
+    /// Called on unmount; providers that persist to disk flush here. No-op by default.
+    fn destroy(&mut self) {}
+    /// Called on an explicit `fsync(2)`/`fdatasync(2)` from an application;
+    /// providers backed by a WAL or other write-behind log checkpoint here.
+    /// No-op by default.
+    fn fsync(&mut self, _ino: u64, _datasync: bool, reply: fuser::ReplyEmpty) {
+        reply.ok();
+    }
+    fn statfs(&mut self, ino: u64, reply: ReplyStatfs);
+    fn setxattr(&mut self, ino: u64, name: &OsStr, value: &[u8], flags: i32, reply: fuser::ReplyEmpty);
+    fn getxattr(&mut self, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr);
+    fn listxattr(&mut self, ino: u64, size: u32, reply: ReplyXattr);
+    fn removexattr(&mut self, ino: u64, name: &OsStr, reply: fuser::ReplyEmpty);
+    fn mknod(&mut self, parent: u64, name: &OsStr, mode: u32, umask: u32, rdev: u32, reply: ReplyEntry);
+    /// Adds `newname` under `newparent` as a second name for `ino`, bumping
+    /// its `nlink`.
+    fn link(&mut self, ino: u64, newparent: u64, newname: &OsStr, reply: ReplyEntry);
+    /// Balances the lookup references the kernel accumulated via `lookup`,
+    /// `create`, `mkdir`, `mknod`, `symlink` and `link`; once an inode has
+    /// neither directory entries (`nlink == 0`) nor outstanding lookups, the
+    /// provider is free to reclaim it.
+    fn forget(&mut self, ino: u64, nlookup: u64);
+}
\ No newline at end of file
@@ -7,6 +7,82 @@ use std::ffi::OsStr;
 
 const ROOT_INODE: u64 = 1;
 const USER_INODE_START: u64 = 10; // user files/dirs start here to avoid reserved inodes
+const STATFS_BLOCK_SIZE: u32 = 512;
+/// Capacity ceiling `statfs` reports the tree as having, matching the
+/// same idiom used by `MemoryProvider`.
+const DEFAULT_CAPACITY_BYTES: u64 = 1024 * 1024 * 1024 * 1024;
+
+/// Content-defined chunking window, mirroring the min/max clamp `MemoryProvider`
+/// uses for its own `ChunkStore`: boundaries are cut on a rolling-hash condition
+/// but never let a chunk shrink below `CDC_MIN_SIZE` or grow past `CDC_MAX_SIZE`.
+const CDC_MIN_SIZE: usize = 2 * 1024;
+const CDC_MAX_SIZE: usize = 64 * 1024;
+/// Rolling-hash window width used to decide chunk boundaries.
+const CDC_WINDOW: usize = 48;
+/// Cut whenever the low `CDC_MASK_BITS` bits of the rolling hash are zero,
+/// which targets an ~8 KiB average chunk size.
+const CDC_MASK_BITS: u32 = 13;
+
+/// Splits `data` into content-defined chunks so that inserting or deleting
+/// bytes in the middle of a file only perturbs the chunks touching the edit,
+/// letting unrelated chunks (and therefore their `chunks` rows) stay shared
+/// across files and across edits of the same file.
+fn cdc_split(data: &[u8]) -> Vec<&[u8]> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let mut boundaries = Vec::new();
+    let mask = (1u64 << CDC_MASK_BITS) - 1;
+    let mut start = 0usize;
+    let mut hash: u64 = 0;
+    for i in 0..data.len() {
+        hash = hash.rotate_left(1) ^ (data[i] as u64);
+        let len = i - start + 1;
+        if len > CDC_WINDOW {
+            hash ^= (data[i - CDC_WINDOW] as u64).rotate_left(CDC_WINDOW as u32);
+        }
+        if len >= CDC_WINDOW && len >= CDC_MIN_SIZE && (hash & mask) == 0 {
+            boundaries.push(i + 1);
+            start = i + 1;
+            hash = 0;
+        } else if len >= CDC_MAX_SIZE {
+            boundaries.push(i + 1);
+            start = i + 1;
+            hash = 0;
+        }
+    }
+    if start < data.len() {
+        boundaries.push(data.len());
+    }
+    let mut slices = Vec::with_capacity(boundaries.len());
+    let mut prev = 0usize;
+    for end in boundaries {
+        slices.push(&data[prev..end]);
+        prev = end;
+    }
+    slices
+}
+
+/// Checks `req_uid`/`req_gid` against `attr`'s owner/group/other rwx bits,
+/// the standard POSIX rule the kernel would otherwise enforce itself when
+/// the mount sets `default_permissions`. `mask` uses the `libc::{R,W,X}_OK`
+/// bits from `access(2)`.
+fn check_access(attr: &fuser::FileAttr, req_uid: u32, req_gid: u32, mask: i32) -> bool {
+    if mask == libc::F_OK {
+        return true;
+    }
+    if req_uid == 0 {
+        return true;
+    }
+    let bits = if req_uid == attr.uid {
+        (attr.perm >> 6) & 0o7
+    } else if req_gid == attr.gid {
+        (attr.perm >> 3) & 0o7
+    } else {
+        attr.perm & 0o7
+    } as i32;
+    (bits & mask) == mask
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 enum FileTypeRepr {
@@ -114,6 +190,8 @@ pub struct SqliteProvider {
     conn: Connection,
     next_inode: u64,
     pub osx_mode: bool,
+    /// Ceiling `statfs` reports the tree as having; see `DEFAULT_CAPACITY_BYTES`.
+    pub capacity_bytes: u64,
 }
 
 impl SqliteProvider {
@@ -123,22 +201,46 @@ impl SqliteProvider {
     }
     pub fn new_with_mode(db_path: &str, osx_mode: bool) -> Result<Self> {
         let conn = Connection::open(db_path)?;
+        // WAL lets readers proceed during a writer's transaction and, combined
+        // with `synchronous=NORMAL`, only risks losing the most recent commit
+        // (never corrupting the database) if the process crashes.
+        conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA synchronous=NORMAL;")?;
         conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS files (
+            "CREATE TABLE IF NOT EXISTS inodes (
                 ino INTEGER PRIMARY KEY,
-                name TEXT NOT NULL,
-                parent INTEGER,
                 is_dir INTEGER NOT NULL,
                 data BLOB,
                 attr BLOB
             );
-            CREATE INDEX IF NOT EXISTS idx_files_parent_name ON files(parent, name);
-            CREATE INDEX IF NOT EXISTS idx_files_parent ON files(parent);
-            CREATE INDEX IF NOT EXISTS idx_files_name ON files(name);"
+            CREATE TABLE IF NOT EXISTS dentries (
+                parent INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                ino INTEGER NOT NULL,
+                PRIMARY KEY (parent, name)
+            );
+            CREATE INDEX IF NOT EXISTS idx_dentries_ino ON dentries(ino);
+            CREATE TABLE IF NOT EXISTS chunks (
+                hash BLOB PRIMARY KEY,
+                data BLOB NOT NULL,
+                refcount INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS file_chunks (
+                ino INTEGER NOT NULL,
+                seq INTEGER NOT NULL,
+                hash BLOB NOT NULL,
+                PRIMARY KEY (ino, seq)
+            );
+            CREATE INDEX IF NOT EXISTS idx_file_chunks_ino ON file_chunks(ino);
+            CREATE TABLE IF NOT EXISTS xattrs (
+                ino INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (ino, name)
+            );"
         )?;
         // Ensure root exists
         {
-            let mut stmt = conn.prepare("SELECT COUNT(*) FROM files WHERE ino = ?1")?;
+            let mut stmt = conn.prepare("SELECT COUNT(*) FROM inodes WHERE ino = ?1")?;
             let count: i64 = stmt.query_row(params![ROOT_INODE], |row| row.get(0))?;
             if count == 0 {
                 let now = SystemTime::now();
@@ -161,14 +263,14 @@ impl SqliteProvider {
                 };
                 let attr_bytes = bincode::serialize(&SerializableFileAttr::from(&attr)).unwrap();
                 conn.execute(
-                    "INSERT INTO files (ino, name, parent, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-                    params![ROOT_INODE, "/", None::<u64>, 1, None::<Vec<u8>>, attr_bytes],
+                    "INSERT INTO inodes (ino, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4)",
+                    params![ROOT_INODE, 1, None::<Vec<u8>>, attr_bytes],
                 )?;
             }
         }
         // Find max inode
         let mut next_inode: u64 = conn.query_row(
-            "SELECT MAX(ino) FROM files",
+            "SELECT MAX(ino) FROM inodes",
             [],
             |row| row.get::<_, Option<u64>>(0),
         )?.unwrap_or(ROOT_INODE);
@@ -177,7 +279,7 @@ impl SqliteProvider {
         } else {
             next_inode += 1;
         }
-        Ok(Self { conn, next_inode, osx_mode })
+        Ok(Self { conn, next_inode, osx_mode, capacity_bytes: DEFAULT_CAPACITY_BYTES })
     }
     fn alloc_inode(&mut self) -> u64 {
         let ino = self.next_inode;
@@ -185,51 +287,165 @@ impl SqliteProvider {
         ino
     }
     fn get_attr(&self, ino: u64) -> Option<fuser::FileAttr> {
-        self.conn.query_row(
-            "SELECT attr FROM files WHERE ino = ?1",
+        Self::get_attr_conn(&self.conn, ino)
+    }
+    /// Same as `get_attr` but takes an explicit `conn` so callers can run it
+    /// inside an in-flight `Transaction` (which derefs to `Connection`).
+    fn get_attr_conn(conn: &Connection, ino: u64) -> Option<fuser::FileAttr> {
+        conn.query_row(
+            "SELECT attr FROM inodes WHERE ino = ?1",
             params![ino],
             |row| {
                 let attr_blob: Vec<u8> = row.get(0)?;
-                let ser_attr: crate::providers::sqlite_simple::SerializableFileAttr = bincode::deserialize(&attr_blob).unwrap();
+                let ser_attr: SerializableFileAttr = bincode::deserialize(&attr_blob).unwrap();
                 Ok(fuser::FileAttr::from(&ser_attr))
             },
         ).optional().unwrap_or(None)
     }
     fn set_attr(&self, ino: u64, attr: &fuser::FileAttr) {
+        Self::set_attr_conn(&self.conn, ino, attr)
+    }
+    /// Same as `set_attr` but takes an explicit `conn`; see `get_attr_conn`.
+    fn set_attr_conn(conn: &Connection, ino: u64, attr: &fuser::FileAttr) {
         let attr_bytes = bincode::serialize(&SerializableFileAttr::from(attr)).unwrap();
-        let _ = self.conn.execute(
-            "UPDATE files SET attr = ?1 WHERE ino = ?2",
+        let _ = conn.execute(
+            "UPDATE inodes SET attr = ?1 WHERE ino = ?2",
             params![attr_bytes, ino],
         );
     }
-    fn get_file_data(&self, ino: u64) -> Option<Vec<u8>> {
-        self.conn.query_row(
-            "SELECT data FROM files WHERE ino = ?1",
-            params![ino],
-            |row| row.get(0),
-        ).optional().unwrap_or(None)
+    /// Ordered content hashes of `ino`'s chunks, read from its `file_chunks`
+    /// rows in `seq` order. Takes an explicit `conn` so
+    /// callers can run it either directly against `self.conn` or inside an
+    /// in-flight `Transaction` (which derefs to `Connection`).
+    fn chunk_hashes(conn: &Connection, ino: u64) -> Vec<Vec<u8>> {
+        let mut stmt = conn
+            .prepare("SELECT hash FROM file_chunks WHERE ino = ?1 ORDER BY seq")
+            .unwrap();
+        stmt.query_map(params![ino], |row| row.get(0))
+            .unwrap()
+            .filter_map(|r| r.ok())
+            .collect()
     }
-    fn set_file_data(&self, ino: u64, data: &[u8]) {
-        let _ = self.conn.execute(
-            "UPDATE files SET data = ?1 WHERE ino = ?2",
-            params![data, ino],
-        );
+    /// Reassembles a file's full contents by concatenating its chunks in order.
+    fn get_chunked_data(conn: &Connection, ino: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for hash in Self::chunk_hashes(conn, ino) {
+            let chunk: Vec<u8> = conn
+                .query_row("SELECT data FROM chunks WHERE hash = ?1", params![hash], |row| row.get(0))
+                .unwrap_or_default();
+            buf.extend_from_slice(&chunk);
+        }
+        buf
+    }
+    /// Interns `data` in the `chunks` table, bumping its refcount if already
+    /// present, and returns its blake3 hash.
+    fn intern_chunk(conn: &Connection, data: &[u8]) -> Vec<u8> {
+        let hash = blake3::hash(data).as_bytes().to_vec();
+        let updated = conn
+            .execute("UPDATE chunks SET refcount = refcount + 1 WHERE hash = ?1", params![hash])
+            .unwrap_or(0);
+        if updated == 0 {
+            let _ = conn.execute(
+                "INSERT INTO chunks (hash, data, refcount) VALUES (?1, ?2, 1)",
+                params![hash, data],
+            );
+        }
+        hash
+    }
+    /// Drops one reference to `hash`, removing the chunk once nothing
+    /// references it anymore.
+    fn release_chunk(conn: &Connection, hash: &[u8]) {
+        let _ = conn.execute("UPDATE chunks SET refcount = refcount - 1 WHERE hash = ?1", params![hash]);
+        let _ = conn.execute("DELETE FROM chunks WHERE hash = ?1 AND refcount <= 0", params![hash]);
+    }
+    /// Re-chunks `data` with `cdc_split` and rewrites `ino`'s `file_chunks`
+    /// rows to match, interning the new pieces before releasing the old
+    /// ones so chunks shared between the two versions stay live throughout.
+    /// Callers run this inside a transaction so a crash mid-rewrite can
+    /// never leave `file_chunks` pointing at a mix of old and new chunks.
+    fn set_chunked_data(conn: &Connection, ino: u64, data: &[u8]) {
+        let old_hashes = Self::chunk_hashes(conn, ino);
+        let _ = conn.execute("DELETE FROM file_chunks WHERE ino = ?1", params![ino]);
+        for (seq, chunk) in cdc_split(data).into_iter().enumerate() {
+            let hash = Self::intern_chunk(conn, chunk);
+            let _ = conn.execute(
+                "INSERT INTO file_chunks (ino, seq, hash) VALUES (?1, ?2, ?3)",
+                params![ino, seq as i64, hash],
+            );
+        }
+        for hash in old_hashes {
+            Self::release_chunk(conn, &hash);
+        }
+    }
+    /// Releases every chunk `ino` references, used once `nlink` reaches zero.
+    fn delete_chunked_data(conn: &Connection, ino: u64) {
+        let old_hashes = Self::chunk_hashes(conn, ino);
+        let _ = conn.execute("DELETE FROM file_chunks WHERE ino = ?1", params![ino]);
+        for hash in old_hashes {
+            Self::release_chunk(conn, &hash);
+        }
+    }
+    /// Issues a WAL checkpoint so data written since the last checkpoint is
+    /// folded back into the main database file, matching what an explicit
+    /// `fsync`/`flush` from an application expects of a durable write.
+    fn checkpoint(&self) {
+        let _ = self.conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);");
+    }
+    /// Inserts a freshly allocated inode and its directory entry as a single
+    /// transaction, used by `mkdir`, `create`, and `symlink` so a crash
+    /// between the two inserts can't leave an unreachable inode or a dentry
+    /// pointing at nothing.
+    fn insert_inode_and_dentry(&mut self, ino: u64, is_dir: bool, data: Option<&[u8]>, attr: &fuser::FileAttr, parent: u64, name: &str) {
+        let attr_bytes = bincode::serialize(&SerializableFileAttr::from(attr)).unwrap();
+        let tx = self.conn.transaction().unwrap();
+        tx.execute(
+            "INSERT INTO inodes (ino, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4)",
+            params![ino, is_dir as i64, data, attr_bytes],
+        ).unwrap();
+        tx.execute(
+            "INSERT INTO dentries (parent, name, ino) VALUES (?1, ?2, ?3)",
+            params![parent, name, ino],
+        ).unwrap();
+        tx.commit().unwrap();
     }
     fn get_child_ino(&self, parent: u64, name: &str) -> Option<u64> {
         self.conn.query_row(
-            "SELECT ino FROM files WHERE parent = ?1 AND name = ?2",
+            "SELECT ino FROM dentries WHERE parent = ?1 AND name = ?2",
             params![parent, name],
             |row| row.get(0),
         ).optional().unwrap_or(None)
     }
     fn is_dir_empty(&self, ino: u64) -> bool {
         let count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM files WHERE parent = ?1",
+            "SELECT COUNT(*) FROM dentries WHERE parent = ?1",
             params![ino],
             |row| row.get(0),
         ).unwrap_or(0);
         count == 0
     }
+    /// Removes the `(parent, name)` dentry pointing at `ino` and decrements
+    /// its `nlink`, reclaiming the inode row (and its chunks/xattrs) only
+    /// once no directory entry references it anymore. Runs as a single
+    /// transaction so a crash mid-unlink can't leave the dentry gone but the
+    /// inode (or its chunks) still around, or vice versa.
+    fn remove_dentry(&mut self, parent: u64, name: &str, ino: u64) {
+        let mut attr = match self.get_attr(ino) {
+            Some(attr) => attr,
+            None => return,
+        };
+        let tx = self.conn.transaction().unwrap();
+        tx.execute("DELETE FROM dentries WHERE parent = ?1 AND name = ?2", params![parent, name]).unwrap();
+        attr.nlink = attr.nlink.saturating_sub(1);
+        if attr.nlink == 0 || attr.kind == fuser::FileType::Directory {
+            Self::delete_chunked_data(&tx, ino);
+            tx.execute("DELETE FROM inodes WHERE ino = ?1", params![ino]).unwrap();
+            tx.execute("DELETE FROM xattrs WHERE ino = ?1", params![ino]).unwrap();
+        } else {
+            let attr_bytes = bincode::serialize(&SerializableFileAttr::from(&attr)).unwrap();
+            tx.execute("UPDATE inodes SET attr = ?1 WHERE ino = ?2", params![attr_bytes, ino]).unwrap();
+        }
+        tx.commit().unwrap();
+    }
 }
 
 impl Provider for SqliteProvider {
@@ -243,19 +459,27 @@ impl Provider for SqliteProvider {
         if !self.is_dir_empty(ino) {
             reply.error(libc::ENOTEMPTY); return;
         }
-        let _ = self.conn.execute("DELETE FROM files WHERE ino = ?1", params![ino]);
-        let _ = self.conn.execute("DELETE FROM files WHERE parent = ?1 AND name = ?2", params![parent, name_str]);
+        self.remove_dentry(parent, name_str, ino);
         reply.ok();
     }
-    fn open(&mut self, ino: u64, reply: fuser::ReplyOpen) {
+    fn open(&mut self, req_uid: u32, req_gid: u32, ino: u64, reply: fuser::ReplyOpen) {
+        match self.get_attr(ino) {
+            Some(attr) if check_access(&attr, req_uid, req_gid, libc::R_OK) => reply.opened(0, 0),
+            Some(_) => reply.error(libc::EACCES),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+    fn flush(&mut self, ino: u64, reply: fuser::ReplyEmpty) {
         if self.get_attr(ino).is_some() {
-            reply.opened(0, 0);
+            self.checkpoint();
+            reply.ok();
         } else {
             reply.error(libc::ENOENT);
         }
     }
-    fn flush(&mut self, ino: u64, reply: fuser::ReplyEmpty) {
+    fn fsync(&mut self, ino: u64, _datasync: bool, reply: fuser::ReplyEmpty) {
         if self.get_attr(ino).is_some() {
+            self.checkpoint();
             reply.ok();
         } else {
             reply.error(libc::ENOENT);
@@ -285,12 +509,16 @@ impl Provider for SqliteProvider {
             if let Some(cr) = crtime { attr.crtime = cr; }
             if let Some(fg) = flags { attr.flags = fg; }
             if let Some(new_size) = size {
-                let mut data = self.get_file_data(ino).unwrap_or_default();
-                data.resize(new_size as usize, 0);
-                self.set_file_data(ino, &data);
                 attr.size = new_size;
+                let tx = self.conn.transaction().unwrap();
+                let mut data = Self::get_chunked_data(&tx, ino);
+                data.resize(new_size as usize, 0);
+                Self::set_chunked_data(&tx, ino, &data);
+                Self::set_attr_conn(&tx, ino, &attr);
+                tx.commit().unwrap();
+            } else {
+                self.set_attr(ino, &attr);
             }
-            self.set_attr(ino, &attr);
             reply.attr(&std::time::Duration::from_secs(1), &attr);
         } else {
             reply.error(libc::ENOENT);
@@ -316,12 +544,16 @@ impl Provider for SqliteProvider {
     }
     fn readdir(&mut self, ino: u64, offset: i64, mut reply: fuser::ReplyDirectory) {
         let mut entries = vec![(ROOT_INODE, fuser::FileType::Directory, ".".to_string()), (ROOT_INODE, fuser::FileType::Directory, "..".to_string())];
-        let mut stmt = self.conn.prepare("SELECT ino, name, is_dir FROM files WHERE parent = ?1").unwrap();
+        let mut stmt = self.conn.prepare(
+            "SELECT dentries.ino, dentries.name, inodes.attr FROM dentries
+             JOIN inodes ON inodes.ino = dentries.ino WHERE dentries.parent = ?1"
+        ).unwrap();
         let rows = stmt.query_map(params![ino], |row| {
             let ino: u64 = row.get(0)?;
             let name: String = row.get(1)?;
-            let is_dir: i64 = row.get(2)?;
-            let kind = if is_dir == 1 { fuser::FileType::Directory } else { fuser::FileType::RegularFile };
+            let attr_blob: Vec<u8> = row.get(2)?;
+            let ser_attr: SerializableFileAttr = bincode::deserialize(&attr_blob).unwrap();
+            let kind = fuser::FileType::from(ser_attr.kind);
             Ok((ino, kind, name))
         }).unwrap();
         for row in rows {
@@ -366,11 +598,7 @@ impl Provider for SqliteProvider {
             flags: 0,
             blksize: 512,
         };
-        let attr_bytes = bincode::serialize(&SerializableFileAttr::from(&attr)).unwrap();
-        let _ = self.conn.execute(
-            "INSERT INTO files (ino, name, parent, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![ino, name_str, parent, 1, None::<Vec<u8>>, attr_bytes],
-        );
+        self.insert_inode_and_dentry(ino, true, None, &attr, parent, name_str);
         reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
     }
     fn create(&mut self, parent: u64, name: &OsStr, mode: u32, _flags: u32, umask: i32, reply: fuser::ReplyCreate) {
@@ -401,38 +629,44 @@ impl Provider for SqliteProvider {
             flags: 0,
             blksize: 512,
         };
-        let attr_bytes = bincode::serialize(&SerializableFileAttr::from(&attr)).unwrap();
-        let _ = self.conn.execute(
-            "INSERT INTO files (ino, name, parent, is_dir, data, attr) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![ino, name_str, parent, 0, Vec::<u8>::new(), attr_bytes],
-        );
+        self.insert_inode_and_dentry(ino, false, None, &attr, parent, name_str);
         reply.created(&std::time::Duration::from_secs(1), &attr, 0, 0, 0);
     }
-    fn read(&mut self, ino: u64, offset: i64, size: u32, reply: fuser::ReplyData) {
-        if let Some(data) = self.get_file_data(ino) {
-            let end = std::cmp::min((offset as usize) + (size as usize), data.len());
-            let start = std::cmp::min(offset as usize, data.len());
-            reply.data(&data[start..end]);
-        } else {
-            reply.error(libc::ENOENT);
+    fn read(&mut self, req_uid: u32, req_gid: u32, ino: u64, offset: i64, size: u32, reply: fuser::ReplyData) {
+        match self.get_attr(ino) {
+            Some(attr) if !check_access(&attr, req_uid, req_gid, libc::R_OK) => {
+                reply.error(libc::EACCES);
+            }
+            Some(_) => {
+                let data = Self::get_chunked_data(&self.conn, ino);
+                let end = std::cmp::min((offset as usize) + (size as usize), data.len());
+                let start = std::cmp::min(offset as usize, data.len());
+                reply.data(&data[start..end]);
+            }
+            None => reply.error(libc::ENOENT),
         }
     }
-    fn write(&mut self, ino: u64, offset: i64, data: &[u8], reply: fuser::ReplyWrite) {
-        if let Some(mut file_data) = self.get_file_data(ino) {
-            let offset = offset as usize;
-            if file_data.len() < offset + data.len() {
-                file_data.resize(offset + data.len(), 0);
-            }
-            file_data[offset..offset + data.len()].copy_from_slice(data);
-            self.set_file_data(ino, &file_data);
-            if let Some(mut attr) = self.get_attr(ino) {
-                attr.size = file_data.len() as u64;
-                self.set_attr(ino, &attr);
+    fn write(&mut self, req_uid: u32, req_gid: u32, ino: u64, offset: i64, data: &[u8], reply: fuser::ReplyWrite) {
+        let mut attr = match self.get_attr(ino) {
+            Some(attr) if !check_access(&attr, req_uid, req_gid, libc::W_OK) => {
+                reply.error(libc::EACCES);
+                return;
             }
-            reply.written(data.len() as u32);
-        } else {
-            reply.error(libc::ENOENT);
+            Some(attr) => attr,
+            None => { reply.error(libc::ENOENT); return; }
+        };
+        let tx = self.conn.transaction().unwrap();
+        let mut file_data = Self::get_chunked_data(&tx, ino);
+        let offset = offset as usize;
+        if file_data.len() < offset + data.len() {
+            file_data.resize(offset + data.len(), 0);
         }
+        file_data[offset..offset + data.len()].copy_from_slice(data);
+        attr.size = file_data.len() as u64;
+        Self::set_chunked_data(&tx, ino, &file_data);
+        Self::set_attr_conn(&tx, ino, &attr);
+        tx.commit().unwrap();
+        reply.written(data.len() as u32);
     }
     fn unlink(&mut self, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
         let name_str = name.to_str().unwrap_or("");
@@ -441,7 +675,227 @@ impl Provider for SqliteProvider {
             Some(ino) => ino,
             None => { reply.error(libc::ENOENT); return; }
         };
-        let _ = self.conn.execute("DELETE FROM files WHERE ino = ?1", params![ino]);
+        self.remove_dentry(parent, name_str, ino);
         reply.ok();
     }
-} 
\ No newline at end of file
+    fn rename(&mut self, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr, _flags: u32, reply: fuser::ReplyEmpty) {
+        let name_str = name.to_str().unwrap_or("");
+        let newname_str = newname.to_str().unwrap_or("");
+        let ino = match self.get_child_ino(parent, name_str) {
+            Some(ino) => ino,
+            None => { reply.error(libc::ENOENT); return; }
+        };
+        if let Some(dest_ino) = self.get_child_ino(newparent, newname_str) {
+            if let Some(attr) = self.get_attr(dest_ino) {
+                if attr.kind == fuser::FileType::Directory && !self.is_dir_empty(dest_ino) {
+                    reply.error(libc::ENOTEMPTY);
+                    return;
+                }
+            }
+            self.remove_dentry(newparent, newname_str, dest_ino);
+        }
+        let res = self.conn.execute(
+            "UPDATE dentries SET parent = ?1, name = ?2 WHERE parent = ?3 AND name = ?4",
+            params![newparent, newname_str, parent, name_str],
+        );
+        if res.is_ok() {
+            reply.ok();
+        } else {
+            reply.error(libc::EIO);
+        }
+    }
+    fn symlink(&mut self, parent: u64, name: &OsStr, link: &std::path::Path, reply: fuser::ReplyEntry) {
+        let name_str = name.to_str().unwrap_or("");
+        if self.osx_mode && name_str.starts_with("._") {
+            reply.error(libc::EACCES);
+            return;
+        }
+        if self.get_child_ino(parent, name_str).is_some() {
+            reply.error(libc::EEXIST); return;
+        }
+        let ino = self.alloc_inode();
+        let now = SystemTime::now();
+        let target = link.to_string_lossy().to_string().into_bytes();
+        let attr = fuser::FileAttr {
+            ino,
+            size: target.len() as u64,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            crtime: now,
+            kind: fuser::FileType::Symlink,
+            perm: 0o777,
+            nlink: 1,
+            uid: unsafe { libc::geteuid() },
+            gid: unsafe { libc::getegid() },
+            rdev: 0,
+            flags: 0,
+            blksize: 512,
+        };
+        self.insert_inode_and_dentry(ino, false, Some(&target), &attr, parent, name_str);
+        reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
+    }
+    fn link(&mut self, ino: u64, newparent: u64, newname: &OsStr, reply: fuser::ReplyEntry) {
+        let name_str = newname.to_str().unwrap_or("");
+        if self.get_child_ino(newparent, name_str).is_some() {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        let mut attr = match self.get_attr(ino) {
+            Some(attr) => attr,
+            None => { reply.error(libc::ENOENT); return; }
+        };
+        if attr.kind == fuser::FileType::Directory {
+            reply.error(libc::EPERM);
+            return;
+        }
+        attr.nlink += 1;
+        let tx = self.conn.transaction().unwrap();
+        Self::set_attr_conn(&tx, ino, &attr);
+        tx.execute(
+            "INSERT INTO dentries (parent, name, ino) VALUES (?1, ?2, ?3)",
+            params![newparent, name_str, ino],
+        ).unwrap();
+        tx.commit().unwrap();
+        reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
+    }
+    fn readlink(&mut self, ino: u64, reply: fuser::ReplyData) {
+        let attr = self.get_attr(ino);
+        if let Some(attr) = attr {
+            if attr.kind == fuser::FileType::Symlink {
+                let data: Option<Vec<u8>> = self.conn.query_row(
+                    "SELECT data FROM inodes WHERE ino = ?1",
+                    params![ino],
+                    |row| row.get(0),
+                ).optional().unwrap_or(None);
+                if let Some(data) = data {
+                    reply.data(&data);
+                    return;
+                }
+            }
+        }
+        reply.error(libc::EINVAL);
+    }
+    fn statfs(&mut self, _ino: u64, reply: fuser::ReplyStatfs) {
+        let files: u64 = self.conn.query_row("SELECT COUNT(*) FROM inodes", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as u64).unwrap_or(0);
+        // Deduplicated chunk bytes plus whatever still lives inline in
+        // `inodes.data` (symlink targets), i.e. the actual resident size
+        // rather than the sum of every file's nominal size.
+        let chunk_bytes: u64 = self.conn.query_row("SELECT COALESCE(SUM(LENGTH(data)), 0) FROM chunks", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as u64).unwrap_or(0);
+        let inline_bytes: u64 = self.conn.query_row("SELECT COALESCE(SUM(LENGTH(data)), 0) FROM inodes", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as u64).unwrap_or(0);
+        let used_bytes = chunk_bytes + inline_bytes;
+        let total_blocks = self.capacity_bytes / STATFS_BLOCK_SIZE as u64;
+        let used_blocks = (used_bytes + STATFS_BLOCK_SIZE as u64 - 1) / STATFS_BLOCK_SIZE as u64;
+        let free_blocks = total_blocks.saturating_sub(used_blocks);
+        const FFREE_SENTINEL: u64 = 1_000_000;
+        reply.statfs(
+            total_blocks,
+            free_blocks,
+            free_blocks,
+            files,
+            FFREE_SENTINEL,
+            STATFS_BLOCK_SIZE,
+            255,
+            STATFS_BLOCK_SIZE,
+        );
+    }
+    fn setxattr(&mut self, ino: u64, name: &OsStr, value: &[u8], flags: i32, reply: fuser::ReplyEmpty) {
+        if self.get_attr(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name_str = name.to_str().unwrap_or("");
+        let exists: bool = self.conn.query_row(
+            "SELECT COUNT(*) FROM xattrs WHERE ino = ?1 AND name = ?2",
+            params![ino, name_str],
+            |row| row.get::<_, i64>(0),
+        ).map(|count| count > 0).unwrap_or(false);
+        if flags & libc::XATTR_CREATE != 0 && exists {
+            reply.error(libc::EEXIST);
+            return;
+        }
+        if flags & libc::XATTR_REPLACE != 0 && !exists {
+            reply.error(libc::ENODATA);
+            return;
+        }
+        let _ = self.conn.execute(
+            "INSERT INTO xattrs (ino, name, value) VALUES (?1, ?2, ?3)
+             ON CONFLICT(ino, name) DO UPDATE SET value = excluded.value",
+            params![ino, name_str, value],
+        );
+        reply.ok();
+    }
+    fn getxattr(&mut self, ino: u64, name: &OsStr, size: u32, reply: fuser::ReplyXattr) {
+        if self.get_attr(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name_str = name.to_str().unwrap_or("");
+        let value: Option<Vec<u8>> = self.conn.query_row(
+            "SELECT value FROM xattrs WHERE ino = ?1 AND name = ?2",
+            params![ino, name_str],
+            |row| row.get(0),
+        ).optional().unwrap_or(None);
+        match value {
+            Some(value) => {
+                if size == 0 {
+                    reply.size(value.len() as u32);
+                } else if value.len() > size as usize {
+                    reply.error(libc::ERANGE);
+                } else {
+                    reply.data(&value);
+                }
+            }
+            None => reply.error(libc::ENODATA),
+        }
+    }
+    fn listxattr(&mut self, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        if self.get_attr(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let mut stmt = self.conn.prepare("SELECT name FROM xattrs WHERE ino = ?1").unwrap();
+        let names: Vec<String> = stmt.query_map(params![ino], |row| row.get(0)).unwrap()
+            .filter_map(|r| r.ok())
+            .collect();
+        let mut buf = Vec::new();
+        for name in names {
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(0);
+        }
+        if size == 0 {
+            reply.size(buf.len() as u32);
+        } else if buf.len() > size as usize {
+            reply.error(libc::ERANGE);
+        } else {
+            reply.data(&buf);
+        }
+    }
+    fn removexattr(&mut self, ino: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        if self.get_attr(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        let name_str = name.to_str().unwrap_or("");
+        let changed = self.conn.execute(
+            "DELETE FROM xattrs WHERE ino = ?1 AND name = ?2",
+            params![ino, name_str],
+        ).unwrap_or(0);
+        if changed > 0 {
+            reply.ok();
+        } else {
+            reply.error(libc::ENODATA);
+        }
+    }
+    fn access(&mut self, req_uid: u32, req_gid: u32, ino: u64, mask: i32, reply: fuser::ReplyEmpty) {
+        match self.get_attr(ino) {
+            Some(attr) if check_access(&attr, req_uid, req_gid, mask) => reply.ok(),
+            Some(_) => reply.error(libc::EACCES),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+}
@@ -7,6 +7,7 @@ use prettytable::{Table, Row, Cell};
 use libc;
 use std::os::unix::fs::symlink;
 use rand::{Rng, SeedableRng};
+use rand::seq::SliceRandom;
 use std::sync::{Arc, Barrier};
 use std::thread;
 
@@ -39,9 +40,41 @@ fn run_fuse_with_provider(provider: &str, db_path: Option<&str>) -> std::process
         .expect("Failed to start fuse process")
 }
 
+/// Like `run_fuse_with_provider`, but also exports the mount over 9P2000.L
+/// on `listen_addr` so a kernel 9p client can attach to the same tree.
+fn run_fuse_with_provider_9p(provider: &str, db_path: Option<&str>, listen_addr: &str) -> std::process::Child {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["run", "--quiet", "--", "--mode-osx", "--provider", provider, "--mode-9p", "--listen", listen_addr]);
+    if let Some(path) = db_path {
+        cmd.args(["--db-path", path]);
+    }
+    cmd.stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start fuse process")
+}
+
+/// Like `run_fuse_with_provider`, but also passes `--compress <codec>` so a
+/// `sqlite_chunked`/`sqlite_encrypted` mount stores new chunk data compressed.
+fn run_fuse_with_provider_compressed(provider: &str, db_path: Option<&str>, codec: &str) -> std::process::Child {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["run", "--quiet", "--", "--mode-osx", "--provider", provider, "--compress", codec]);
+    if let Some(path) = db_path {
+        cmd.args(["--db-path", path]);
+    }
+    cmd.stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start fuse process")
+}
+
 fn wait_for_mount() {
+    wait_for_mount_at(MOUNTPOINT);
+}
+
+fn wait_for_mount_at(mountpoint: &str) {
     for _ in 0..40 {
-        if let Ok(mut file) = File::open(format!("{}/.fuse_ready", MOUNTPOINT)) {
+        if let Ok(mut file) = File::open(format!("{}/.fuse_ready", mountpoint)) {
             let mut contents = String::new();
             if file.read_to_string(&mut contents).is_ok() {
                 println!("Found .fuse_ready with contents: {}", contents);
@@ -54,8 +87,12 @@ fn wait_for_mount() {
 }
 
 fn wait_for_unmount() {
+    wait_for_unmount_at(MOUNTPOINT);
+}
+
+fn wait_for_unmount_at(mountpoint: &str) {
     for _ in 0..40 {
-        if std::fs::metadata(format!("{}/.fuse_ready", MOUNTPOINT)).is_err() {
+        if std::fs::metadata(format!("{}/.fuse_ready", mountpoint)).is_err() {
             return;
         }
         std::thread::sleep(Duration::from_millis(100));
@@ -63,9 +100,85 @@ fn wait_for_unmount() {
     panic!("Mountpoint still present or .fuse_ready still exists");
 }
 
+/// Like `run_fuse_with_provider`, but mounts at an explicit mountpoint so
+/// several providers can be mounted side by side for differential testing.
+fn run_fuse_with_provider_at(provider: &str, db_path: Option<&str>, mountpoint: &str) -> std::process::Child {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["run", "--quiet", "--", "--mode-osx", "--provider", provider, "--mountpoint", mountpoint]);
+    if let Some(path) = db_path {
+        cmd.args(["--db-path", path]);
+    }
+    cmd.stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start fuse process")
+}
+
+/// Like `run_fuse_with_provider_at`, but mounts read-only against a past
+/// snapshot era instead of live, so a `--read-snapshot` remount's view can be
+/// compared against the live tree in the same test.
+fn run_fuse_with_provider_at_snapshot(provider: &str, db_path: &str, era: u64, mountpoint: &str) -> std::process::Child {
+    Command::new("cargo")
+        .args(["run", "--quiet", "--", "--mode-osx", "--provider", provider, "--db-path", db_path, "--mountpoint", mountpoint, "--read-snapshot", &era.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start fuse process")
+}
+
+/// Runs a one-shot (non-mounting) CLI subcommand like `check` or `snapshot`
+/// to completion and returns its output, for tests that need to inspect the
+/// printed summary rather than drive a live mount.
+fn run_cli(args: &[&str]) -> std::process::Output {
+    Command::new("cargo")
+        .args(["run", "--quiet", "--"])
+        .args(args)
+        .output()
+        .expect("Failed to run cargo CLI command")
+}
+
+/// Runs `sql` against `db_path` via the `sqlite3` CLI and returns trimmed
+/// stdout, for tests that need to inspect or directly corrupt the on-disk
+/// schema rather than go through the provider.
+fn sqlite3_query(db_path: &str, sql: &str) -> String {
+    let output = Command::new("sqlite3")
+        .args([db_path, sql])
+        .output()
+        .expect("Failed to run sqlite3");
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Like `run_fuse_with_provider`, but mounts (and therefore FUSE-calls) as
+/// `uid`/`gid` instead of this process's own user, via `setpriv`, so a write
+/// genuinely arrives at the provider with a non-root `req_uid`. Returns
+/// `None` if `setpriv` can't be spawned at all (e.g. unavailable in this
+/// environment), so callers can skip rather than fail.
+fn run_fuse_with_provider_as(provider: &str, db_path: Option<&str>, uid: u32, gid: u32) -> Option<std::process::Child> {
+    let mut cmd = Command::new("setpriv");
+    cmd.args(["--reuid", &uid.to_string(), "--regid", &gid.to_string(), "--clear-groups", "--", "cargo", "run", "--quiet", "--", "--mode-osx", "--provider", provider]);
+    if let Some(path) = db_path {
+        cmd.args(["--db-path", path]);
+    }
+    cmd.stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()
+}
+
+/// Changes `path`'s owning user and group via the `chown` CLI, returning
+/// whether it succeeded.
+fn chown_path(path: &str, uid: u32, gid: u32) -> bool {
+    Command::new("chown")
+        .args([&format!("{uid}:{gid}"), path])
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
 fn clean_setup(db_path: Option<&str>) {
     let _ = fs::remove_file("cf-fuse-simple.db");
     let _ = fs::remove_file("cf-fuse-chunked.db");
+    let _ = fs::remove_file("cf-fuse.img");
     if let Some(path) = db_path {
         let _ = fs::remove_file(path);
     }
@@ -349,12 +462,77 @@ fn nested_dir_create_write_read_recursive_delete() -> Result<(), String> {
     Ok(())
 }
 
+fn xattr_set_list_get_remove() -> Result<(), String> {
+    use std::ffi::CString;
+    File::create(TEST_FILE).map_err(|e| format!("create: {e}"))?;
+    let path = CString::new(TEST_FILE).map_err(|e| format!("path cstring: {e}"))?;
+
+    let attrs: [(&str, &[u8]); 3] = [
+        ("user.tag", b"important"),
+        ("user.mime", b"text/plain"),
+        ("user.note", b"stress-test"),
+    ];
+    for (name, value) in attrs.iter() {
+        let cname = CString::new(*name).map_err(|e| format!("name cstring: {e}"))?;
+        let ret = unsafe { libc::setxattr(path.as_ptr(), cname.as_ptr(), value.as_ptr() as *const libc::c_void, value.len(), 0) };
+        if ret != 0 {
+            return Err(format!("setxattr {name}: {}", std::io::Error::last_os_error()));
+        }
+    }
+
+    let list_names = |path: &CString| -> Result<Vec<String>, String> {
+        let mut buf = vec![0u8; 4096];
+        let len = unsafe { libc::listxattr(path.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+        if len < 0 {
+            return Err(format!("listxattr: {}", std::io::Error::last_os_error()));
+        }
+        Ok(buf[..len as usize].split(|&b| b == 0).filter(|s| !s.is_empty())
+            .map(|s| String::from_utf8_lossy(s).to_string()).collect())
+    };
+
+    let listed = list_names(&path)?;
+    for (name, _) in attrs.iter() {
+        if !listed.iter().any(|n| n == name) {
+            return Err(format!("listxattr missing {name}, got {:?}", listed));
+        }
+    }
+
+    for (name, value) in attrs.iter() {
+        let cname = CString::new(*name).map_err(|e| format!("name cstring: {e}"))?;
+        let mut vbuf = vec![0u8; 256];
+        let vlen = unsafe { libc::getxattr(path.as_ptr(), cname.as_ptr(), vbuf.as_mut_ptr() as *mut libc::c_void, vbuf.len()) };
+        if vlen < 0 {
+            return Err(format!("getxattr {name}: {}", std::io::Error::last_os_error()));
+        }
+        if &vbuf[..vlen as usize] != *value {
+            return Err(format!("getxattr {name} returned the wrong value"));
+        }
+    }
+
+    let removed_name = CString::new("user.note").map_err(|e| format!("name cstring: {e}"))?;
+    let ret = unsafe { libc::removexattr(path.as_ptr(), removed_name.as_ptr()) };
+    if ret != 0 {
+        return Err(format!("removexattr: {}", std::io::Error::last_os_error()));
+    }
+    let listed_after_remove = list_names(&path)?;
+    if listed_after_remove.iter().any(|n| n == "user.note") {
+        return Err("user.note still listed after removexattr".to_string());
+    }
+    if listed_after_remove.len() != listed.len() - 1 {
+        return Err(format!("listing did not shrink by exactly one: before {:?}, after {:?}", listed, listed_after_remove));
+    }
+
+    fs::remove_file(TEST_FILE).map_err(|e| format!("remove: {e}"))?;
+    Ok(())
+}
+
 #[test]
 fn integration_stress() {
     let providers = [
         ("memory", "MemoryProvider", None),
         ("sqlite_simple", "SqliteSimpleProvider", Some("test-sqlite-simple.db")),
         ("sqlite_chunked", "SqliteChunkedProvider", Some("test-sqlite-chunked.db")),
+        ("fat", "FatProvider", Some("test-fat.img")),
     ];
     let stress_tests = [
         StressTest { name: "file_create_write_read_delete", func: file_create_write_read_delete, skip_providers: None },
@@ -368,6 +546,7 @@ fn integration_stress() {
         StressTest { name: "concurrent_file_access", func: concurrent_file_access, skip_providers: None },
         StressTest { name: "dir_rename_check_delete", func: dir_rename_check_delete, skip_providers: None },
         StressTest { name: "nested_dir_create_write_read_recursive_delete", func: nested_dir_create_write_read_recursive_delete, skip_providers: None },
+        StressTest { name: "xattr_set_list_get_remove", func: xattr_set_list_get_remove, skip_providers: Some(&["fat"]) },
         // Add more tests here
     ];
     let mut results = vec![vec![]; stress_tests.len()];
@@ -496,4 +675,1007 @@ fn integration_stress() {
     // Final cleanup: remove test DBs if present
     let _ = std::fs::remove_file("test-sqlite-simple.db");
     let _ = std::fs::remove_file("test-sqlite-chunked.db");
-} 
\ No newline at end of file
+    let _ = std::fs::remove_file("test-fat.img");
+}
+
+/// Mounts the memory provider with `--mode-9p`, attaches the Linux kernel 9p
+/// client to the same tree over TCP, and proves the transports agree by
+/// writing through FUSE and reading back through 9P. Skips (rather than
+/// fails) when `mount.9p` isn't available, since most CI/dev boxes don't
+/// have the 9p kernel module loaded.
+#[test]
+fn nine_p_parity() {
+    const MOUNTPOINT_9P: &str = "./mnt9p";
+    let listen_addr = "127.0.0.1:5640";
+    clean_setup(None);
+    let _ = fs::remove_dir_all(MOUNTPOINT_9P);
+    let _ = fs::create_dir_all(MOUNTPOINT_9P);
+
+    let mut child = run_fuse_with_provider_9p("memory", None, listen_addr);
+    wait_for_mount();
+    // Give the 9P listener a moment to bind after the FUSE mount signals ready.
+    thread::sleep(Duration::from_millis(200));
+
+    let mount_status = Command::new("mount")
+        .args(["-t", "9p", "-o", "trans=tcp,port=5640,version=9p2000.L", "127.0.0.1", MOUNTPOINT_9P])
+        .status();
+
+    match mount_status {
+        Ok(s) if s.success() => {
+            let via_fuse = format!("{}/p9test", MOUNTPOINT);
+            let via_9p = format!("{}/p9test", MOUNTPOINT_9P);
+            let data = b"hello over 9p";
+            let write_result = (|| -> Result<(), String> {
+                File::create(&via_fuse).map_err(|e| format!("create: {e}"))?.write_all(data).map_err(|e| format!("write: {e}"))?;
+                let mut buf = Vec::new();
+                File::open(&via_9p).map_err(|e| format!("9p open: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("9p read: {e}"))?;
+                if buf != data {
+                    return Err(format!("9p read back {:?}, expected {:?}", buf, data));
+                }
+                fs::remove_file(&via_fuse).map_err(|e| format!("remove: {e}"))?;
+                Ok(())
+            })();
+            let _ = Command::new("umount").arg(MOUNTPOINT_9P).status();
+            write_result.expect("9P transport diverged from FUSE transport");
+        }
+        _ => {
+            println!("skipping nine_p_parity: `mount -t 9p` unavailable in this environment");
+        }
+    }
+
+    unsafe {
+        libc::kill(child.id() as i32, libc::SIGINT);
+    }
+    let _ = child.wait();
+    wait_for_unmount();
+    let _ = fs::remove_dir_all(MOUNTPOINT_9P);
+}
+
+/// Round-trips data through a `sqlite_chunked` mount with `--compress zstd`,
+/// across a mix of highly-compressible and random chunks spanning several
+/// chunk-size boundaries, proving the codec-tagged blobs decode back to
+/// exactly what was written regardless of which codec (or none) a given
+/// chunk ended up stored with.
+#[test]
+fn compression_roundtrip() {
+    const DB_PATH: &str = "test-sqlite-chunked-compress.db";
+    clean_setup(Some(DB_PATH));
+
+    let mut child = run_fuse_with_provider_compressed("sqlite_chunked", Some(DB_PATH), "zstd");
+    wait_for_mount();
+
+    let result = (|| -> Result<(), String> {
+        let chunk_size = 4096usize;
+        // A few chunks of highly-compressible text, a few chunks of random
+        // bytes, and a final partial chunk, so both codec paths and the
+        // partial-last-chunk length bookkeeping are exercised together.
+        let mut data = Vec::new();
+        data.extend(std::iter::repeat(b"the quick brown fox jumps over the lazy dog\n" as &[u8]).flatten().take(chunk_size * 3));
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let mut random_part = vec![0u8; chunk_size * 2];
+        rng.fill(&mut random_part[..]);
+        data.extend_from_slice(&random_part);
+        data.extend(std::iter::repeat(b'z').take(chunk_size / 2));
+
+        let path = format!("{}/compress_test", MOUNTPOINT);
+        File::create(&path).map_err(|e| format!("create: {e}"))?.write_all(&data).map_err(|e| format!("write: {e}"))?;
+        let mut buf = Vec::new();
+        File::open(&path).map_err(|e| format!("open: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("read: {e}"))?;
+        if buf != data {
+            return Err("full read-back mismatch".to_string());
+        }
+
+        // Re-read a sub-range straddling the compressible/random boundary to
+        // exercise the decode path once more with an arbitrary offset.
+        let mut file = File::open(&path).map_err(|e| format!("reopen: {e}"))?;
+        file.seek(std::io::SeekFrom::Start((chunk_size * 3 - 100) as u64)).map_err(|e| format!("seek: {e}"))?;
+        let mut partial = vec![0u8; 200];
+        file.read_exact(&mut partial).map_err(|e| format!("partial read: {e}"))?;
+        if partial != data[chunk_size * 3 - 100..chunk_size * 3 + 100] {
+            return Err("straddling read-back mismatch".to_string());
+        }
+
+        // Overwrite a byte inside the random region. The file went in as one
+        // whole-file write (the CDC path), so this partial write converts it
+        // to the fixed-offset store under compressed storage.
+        let mut file = OpenOptions::new().write(true).open(&path).map_err(|e| format!("open for write: {e}"))?;
+        file.seek(std::io::SeekFrom::Start((chunk_size * 3 + 10) as u64)).map_err(|e| format!("seek: {e}"))?;
+        file.write_all(&[0xAB]).map_err(|e| format!("overwrite: {e}"))?;
+        drop(file);
+        data[chunk_size * 3 + 10] = 0xAB;
+        let mut buf = Vec::new();
+        File::open(&path).map_err(|e| format!("reopen2: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("reread: {e}"))?;
+        if buf != data {
+            return Err("read-back after overwrite mismatch".to_string());
+        }
+
+        fs::remove_file(&path).map_err(|e| format!("remove: {e}"))?;
+        Ok(())
+    })();
+
+    unsafe {
+        libc::kill(child.id() as i32, libc::SIGINT);
+    }
+    let _ = child.wait();
+    wait_for_unmount();
+    let _ = fs::remove_file(DB_PATH);
+    result.expect("compression round-trip failed");
+}
+
+/// One step of the differential fuzzer. Operates on a small fixed name pool
+/// so every provider sees the exact same preconditions; the generator only
+/// ever emits ops that are valid against `model`, so a well-behaved provider
+/// should never error on any of them.
+#[derive(Clone, Debug)]
+enum FuzzOp {
+    CreateFile(&'static str),
+    WriteAt(&'static str, u64, Vec<u8>),
+    TruncateGrow(&'static str, u64),
+    TruncateShrink(&'static str, u64),
+    Mkdir(&'static str),
+    Rename(&'static str, &'static str),
+    Symlink(&'static str, &'static str),
+    Remove(&'static str),
+    Readdir,
+}
+
+/// Tracks which of `FILE_NAMES`/`DIR_NAMES` currently exist (and each file's
+/// length) purely to keep the generator from emitting an invalid op; it is
+/// not consulted when comparing providers; the providers are each other's
+/// oracle for that.
+#[derive(Default)]
+struct FuzzModel {
+    files: std::collections::HashMap<&'static str, u64>,
+    dirs: std::collections::HashSet<&'static str>,
+}
+
+const FILE_NAMES: &[&str] = &["f0", "f1", "f2", "f3"];
+const DIR_NAMES: &[&str] = &["d0", "d1"];
+
+/// Generates `count` well-formed ops by repeatedly picking an op kind at
+/// random and retrying against a different kind if the chosen one has no
+/// valid target under the current `model`.
+fn generate_fuzz_ops(rng: &mut impl Rng, model: &mut FuzzModel, count: usize) -> Vec<FuzzOp> {
+    let mut ops = Vec::with_capacity(count);
+    while ops.len() < count {
+        let op = match rng.gen_range(0..8) {
+            0 => {
+                let free: Vec<_> = FILE_NAMES.iter().filter(|n| !model.files.contains_key(*n) && !model.dirs.contains(*n)).collect();
+                match free.choose(rng) {
+                    Some(&&name) => { model.files.insert(name, 0); FuzzOp::CreateFile(name) }
+                    None => continue,
+                }
+            }
+            1 => {
+                let existing: Vec<_> = model.files.keys().copied().collect();
+                match existing.choose(rng) {
+                    Some(&name) => {
+                        let offset = rng.gen_range(0..=model.files[name]);
+                        let len = rng.gen_range(1..=256);
+                        let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+                        model.files.insert(name, (offset + len as u64).max(model.files[name]));
+                        FuzzOp::WriteAt(name, offset, data)
+                    }
+                    None => continue,
+                }
+            }
+            2 => {
+                let existing: Vec<_> = model.files.keys().copied().collect();
+                match existing.choose(rng) {
+                    Some(&name) => {
+                        let new_size = model.files[name] + rng.gen_range(1..=256);
+                        model.files.insert(name, new_size);
+                        FuzzOp::TruncateGrow(name, new_size)
+                    }
+                    None => continue,
+                }
+            }
+            3 => {
+                let existing: Vec<_> = model.files.iter().filter(|(_, &len)| len > 0).map(|(&n, _)| n).collect();
+                match existing.choose(rng) {
+                    Some(&name) => {
+                        let new_size = rng.gen_range(0..model.files[name]);
+                        model.files.insert(name, new_size);
+                        FuzzOp::TruncateShrink(name, new_size)
+                    }
+                    None => continue,
+                }
+            }
+            4 => {
+                let free: Vec<_> = DIR_NAMES.iter().filter(|n| !model.dirs.contains(*n) && !model.files.contains_key(*n)).collect();
+                match free.choose(rng) {
+                    Some(&&name) => { model.dirs.insert(name); FuzzOp::Mkdir(name) }
+                    None => continue,
+                }
+            }
+            5 => {
+                let existing: Vec<_> = model.files.keys().copied().collect();
+                let taken: Vec<_> = FILE_NAMES.iter().filter(|n| !model.files.contains_key(*n) && !model.dirs.contains(*n)).collect();
+                match (existing.choose(rng), taken.choose(rng)) {
+                    (Some(&from), Some(&&to)) => {
+                        let len = model.files.remove(from).unwrap();
+                        model.files.insert(to, len);
+                        FuzzOp::Rename(from, to)
+                    }
+                    _ => continue,
+                }
+            }
+            6 => {
+                let existing: Vec<_> = model.files.keys().copied().collect();
+                let free: Vec<_> = FILE_NAMES.iter().filter(|n| !model.files.contains_key(*n) && !model.dirs.contains(*n)).collect();
+                match (existing.choose(rng), free.choose(rng)) {
+                    (Some(&target), Some(&&link_name)) => {
+                        // Symlinks don't participate in the size/content model.
+                        FuzzOp::Symlink(target, link_name)
+                    }
+                    _ => continue,
+                }
+            }
+            _ => {
+                let existing: Vec<_> = model.files.keys().copied().collect();
+                match existing.choose(rng) {
+                    Some(&name) => { model.files.remove(name); FuzzOp::Remove(name) }
+                    None => continue,
+                }
+            }
+        };
+        ops.push(op);
+    }
+    ops
+}
+
+fn apply_fuzz_op(mountpoint: &str, op: &FuzzOp) -> Result<(), String> {
+    let path = |name: &str| format!("{}/{}", mountpoint, name);
+    match op {
+        FuzzOp::CreateFile(name) => {
+            File::create(path(name)).map_err(|e| format!("create {name}: {e}"))?;
+            Ok(())
+        }
+        FuzzOp::WriteAt(name, offset, data) => {
+            let mut file = OpenOptions::new().write(true).open(path(name)).map_err(|e| format!("open {name}: {e}"))?;
+            file.seek(std::io::SeekFrom::Start(*offset)).map_err(|e| format!("seek {name}: {e}"))?;
+            file.write_all(data).map_err(|e| format!("write {name}: {e}"))?;
+            Ok(())
+        }
+        FuzzOp::TruncateGrow(name, size) | FuzzOp::TruncateShrink(name, size) => {
+            let file = OpenOptions::new().write(true).open(path(name)).map_err(|e| format!("open {name}: {e}"))?;
+            file.set_len(*size).map_err(|e| format!("truncate {name}: {e}"))?;
+            Ok(())
+        }
+        FuzzOp::Mkdir(name) => {
+            create_dir(path(name)).map_err(|e| format!("mkdir {name}: {e}"))?;
+            Ok(())
+        }
+        FuzzOp::Rename(from, to) => {
+            rename(path(from), path(to)).map_err(|e| format!("rename {from}->{to}: {e}"))?;
+            Ok(())
+        }
+        FuzzOp::Symlink(target, link_name) => {
+            symlink(path(target), path(link_name)).map_err(|e| format!("symlink {link_name}: {e}"))?;
+            Ok(())
+        }
+        FuzzOp::Remove(name) => {
+            remove_file(path(name)).map_err(|e| format!("remove {name}: {e}"))?;
+            Ok(())
+        }
+        FuzzOp::Readdir => {
+            read_dir(mountpoint).map_err(|e| format!("readdir: {e}"))?;
+            Ok(())
+        }
+    }
+}
+
+/// Everything observable about a mounted tree after a step: the sorted
+/// listing plus, for every currently-tracked file, its content and `nlink`.
+/// Byte-identical snapshots across providers is the pass condition.
+#[derive(PartialEq, Eq, Debug)]
+struct FuzzSnapshot {
+    listing: Vec<String>,
+    files: Vec<(String, Vec<u8>, u64)>,
+}
+
+fn snapshot_fuzz_tree(mountpoint: &str, model: &FuzzModel) -> Result<FuzzSnapshot, String> {
+    use std::os::unix::fs::MetadataExt;
+    let mut listing: Vec<String> = read_dir(mountpoint).map_err(|e| format!("readdir: {e}"))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    listing.sort();
+    let mut files = Vec::new();
+    let mut names: Vec<_> = model.files.keys().copied().collect();
+    names.sort();
+    for name in names {
+        let p = format!("{}/{}", mountpoint, name);
+        let mut buf = Vec::new();
+        File::open(&p).map_err(|e| format!("open {name}: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("read {name}: {e}"))?;
+        let nlink = metadata(&p).map_err(|e| format!("stat {name}: {e}"))?.nlink();
+        files.push((name.to_string(), buf, nlink));
+    }
+    Ok(FuzzSnapshot { listing, files })
+}
+
+/// Replays one seeded pseudo-random operation sequence against every
+/// provider mounted in parallel and asserts they stay byte-identical after
+/// every step, turning the providers into oracles for each other. On
+/// divergence the seed and op log are printed (so the run is reproducible)
+/// along with a failure-details table naming the op index and the provider
+/// whose snapshot disagreed with the first (memory) provider's.
+#[test]
+fn differential_fuzz() {
+    const SEED: u64 = 0xD0F5_FA22;
+    const OP_COUNT: usize = 200;
+    println!("differential_fuzz seed = {SEED:#x}");
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(SEED);
+    let mut gen_model = FuzzModel::default();
+    let ops = generate_fuzz_ops(&mut rng, &mut gen_model, OP_COUNT);
+
+    let providers = [
+        ("memory", "MemoryProvider", None, "./mnt_fuzz_memory"),
+        ("sqlite_simple", "SqliteSimpleProvider", Some("fuzz-sqlite-simple.db"), "./mnt_fuzz_sqlite_simple"),
+        ("sqlite_chunked", "SqliteChunkedProvider", Some("fuzz-sqlite-chunked.db"), "./mnt_fuzz_sqlite_chunked"),
+        ("fat", "FatProvider", Some("fuzz-fat.img"), "./mnt_fuzz_fat"),
+    ];
+
+    for (_, _, db_path, mountpoint) in providers.iter() {
+        if let Some(path) = db_path {
+            let _ = fs::remove_file(path);
+        }
+        let _ = fs::remove_dir_all(mountpoint);
+        let _ = fs::create_dir_all(mountpoint);
+    }
+
+    let mut children: Vec<_> = providers.iter()
+        .map(|(prov, _, db_path, mountpoint)| run_fuse_with_provider_at(prov, *db_path, mountpoint))
+        .collect();
+    for (_, _, _, mountpoint) in providers.iter() {
+        wait_for_mount_at(mountpoint);
+    }
+
+    let mut model = FuzzModel::default();
+    let mut divergence: Option<(usize, Vec<String>)> = None;
+    for (op_idx, op) in ops.iter().enumerate() {
+        // Advance the model alongside replay so snapshots only inspect
+        // files the op log has actually created by this point.
+        match op {
+            FuzzOp::CreateFile(n) => { model.files.insert(n, 0); }
+            FuzzOp::WriteAt(n, off, data) => { let new_len = (*off + data.len() as u64).max(*model.files.get(n).unwrap_or(&0)); model.files.insert(n, new_len); }
+            FuzzOp::TruncateGrow(n, size) | FuzzOp::TruncateShrink(n, size) => { model.files.insert(n, *size); }
+            FuzzOp::Mkdir(n) => { model.dirs.insert(n); }
+            FuzzOp::Rename(from, to) => { if let Some(len) = model.files.remove(from) { model.files.insert(to, len); } }
+            FuzzOp::Symlink(..) => {}
+            FuzzOp::Remove(n) => { model.files.remove(n); }
+            FuzzOp::Readdir => {}
+        }
+
+        let mut op_errors = Vec::new();
+        for (prov, _, _, mountpoint) in providers.iter() {
+            if let Err(e) = apply_fuzz_op(mountpoint, op) {
+                op_errors.push(format!("{prov}: {e}"));
+            }
+        }
+        if !op_errors.is_empty() {
+            divergence = Some((op_idx, op_errors));
+            break;
+        }
+
+        let snapshots: Vec<Result<FuzzSnapshot, String>> = providers.iter()
+            .map(|(_, _, _, mountpoint)| snapshot_fuzz_tree(mountpoint, &model))
+            .collect();
+        let baseline = match &snapshots[0] {
+            Ok(s) => s,
+            Err(e) => { divergence = Some((op_idx, vec![format!("{}: snapshot failed: {e}", providers[0].0)])); break; }
+        };
+        let mut mismatches = Vec::new();
+        for (i, snap) in snapshots.iter().enumerate().skip(1) {
+            match snap {
+                Ok(s) if s == baseline => {}
+                Ok(s) => mismatches.push(format!("{}: diverged from {} -> {:?} vs {:?}", providers[i].0, providers[0].1, s, baseline)),
+                Err(e) => mismatches.push(format!("{}: snapshot failed: {e}", providers[i].0)),
+            }
+        }
+        if !mismatches.is_empty() {
+            divergence = Some((op_idx, mismatches));
+            break;
+        }
+    }
+
+    for child in children.iter_mut() {
+        unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    }
+    for mut child in children {
+        let _ = child.wait();
+    }
+    for (_, _, _, mountpoint) in providers.iter() {
+        wait_for_unmount_at(mountpoint);
+        let _ = fs::remove_dir_all(mountpoint);
+    }
+    for (_, _, db_path, _) in providers.iter() {
+        if let Some(path) = db_path {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    if let Some((op_idx, reasons)) = divergence {
+        println!("divergence at op {op_idx}: {:?}", ops[op_idx]);
+        println!("full op log (seed {SEED:#x}):");
+        for (i, op) in ops.iter().enumerate() {
+            println!("  [{i}] {:?}", op);
+        }
+        let mut failure_table = Table::new();
+        failure_table.add_row(Row::new(vec![Cell::new("op_index"), Cell::new("provider"), Cell::new("reason")]));
+        for reason in &reasons {
+            failure_table.add_row(Row::new(vec![Cell::new(&op_idx.to_string()), Cell::new("-"), Cell::new(reason)]));
+        }
+        failure_table.printstd();
+        panic!("providers diverged at op {op_idx}: {:?}", reasons);
+    }
+}
+
+/// Takes a snapshot mid-way through a `sqlite_chunked` file's history and
+/// proves the snapshot is immune to a later write: a live remount sees the
+/// new content, while a `--read-snapshot` mount against the same database
+/// still returns exactly what was on disk the moment the snapshot was taken.
+#[test]
+fn snapshot_preserves_historical_read() {
+    const DB_PATH: &str = "test-sqlite-chunked-snapshot.db";
+    const SNAP_MOUNTPOINT: &str = "./mnt_snapshot_read";
+    clean_setup(Some(DB_PATH));
+
+    let original = vec![b'A'; 4096 * 2];
+    let updated = vec![b'B'; 4096 * 2];
+    let path = format!("{}/snapshot_test", MOUNTPOINT);
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let write_result = File::create(&path)
+        .and_then(|mut f| f.write_all(&original))
+        .map_err(|e| format!("initial write: {e}"));
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    write_result.expect("initial write failed");
+
+    let snapshot_output = run_cli(&["snapshot", "--db-path", DB_PATH, "--snapshot"]);
+    let stdout = String::from_utf8_lossy(&snapshot_output.stdout).trim().to_string();
+    let era: u64 = stdout.rsplit(' ').next().and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| panic!("could not parse snapshot era from: {stdout:?}"));
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let rewrite_result = (|| -> Result<(), String> {
+        let mut file = OpenOptions::new().write(true).open(&path).map_err(|e| format!("reopen: {e}"))?;
+        file.write_all(&updated).map_err(|e| format!("rewrite: {e}"))?;
+        drop(file);
+        let mut buf = Vec::new();
+        File::open(&path).map_err(|e| format!("open live: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("read live: {e}"))?;
+        if buf != updated {
+            return Err("live mount did not see the new content after rewrite".to_string());
+        }
+        Ok(())
+    })();
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    rewrite_result.expect("rewrite after snapshot failed");
+
+    let _ = fs::remove_dir_all(SNAP_MOUNTPOINT);
+    let _ = fs::create_dir_all(SNAP_MOUNTPOINT);
+    let mut child = run_fuse_with_provider_at_snapshot("sqlite_chunked", DB_PATH, era, SNAP_MOUNTPOINT);
+    wait_for_mount_at(SNAP_MOUNTPOINT);
+    let historical_result = (|| -> Result<(), String> {
+        let snap_path = format!("{}/snapshot_test", SNAP_MOUNTPOINT);
+        let mut buf = Vec::new();
+        File::open(&snap_path).map_err(|e| format!("open snapshot: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("read snapshot: {e}"))?;
+        if buf != original {
+            return Err(format!("snapshot mount returned {} byte(s) starting {:?}, expected the original pre-snapshot content", buf.len(), buf.first()));
+        }
+        Ok(())
+    })();
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount_at(SNAP_MOUNTPOINT);
+    let _ = fs::remove_dir_all(SNAP_MOUNTPOINT);
+    let _ = fs::remove_file(DB_PATH);
+
+    historical_result.expect("reading the snapshot did not return the historical content");
+}
+
+/// Deliberately corrupts a `sqlite_chunked` database by deleting a file's
+/// inode row out from under its chunks (simulating a crash mid-`unlink`),
+/// then asserts `check --repair` restores a mountable, consistent tree: the
+/// inconsistency is reported, repaired, a second `check` finds nothing left,
+/// and the rest of the tree is untouched and readable.
+#[test]
+fn fsck_repair_orphaned_chunks() {
+    const DB_PATH: &str = "test-sqlite-chunked-fsck.db";
+    clean_setup(Some(DB_PATH));
+
+    let corrupt_path = format!("{}/corrupt_test", MOUNTPOINT);
+    let control_path = format!("{}/control_file", MOUNTPOINT);
+    let control_data = b"fsck control file survives repair";
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let setup_result = (|| -> Result<(), String> {
+        // A single whole-file write would take the CDC dispatch path (see
+        // `write` in sqlite_chunked.rs) and land in `file_chunks`, not
+        // `chunks`. Writing in two separate calls forces the second one
+        // (a non-zero-offset append onto an already-CDC file) through the
+        // CDC-to-fixed-offset conversion, so the data ends up in the legacy
+        // `chunks` table this test corrupts below.
+        let mut corrupt_file = File::create(&corrupt_path).map_err(|e| format!("create corrupt_test: {e}"))?;
+        corrupt_file.write_all(&vec![b'x'; 4096]).map_err(|e| format!("write corrupt_test (1st half): {e}"))?;
+        corrupt_file.write_all(&vec![b'x'; 4096]).map_err(|e| format!("write corrupt_test (2nd half): {e}"))?;
+        File::create(&control_path).map_err(|e| format!("create control_file: {e}"))?
+            .write_all(control_data).map_err(|e| format!("write control_file: {e}"))?;
+        Ok(())
+    })();
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    setup_result.expect("fsck test setup failed");
+
+    let ino = sqlite3_query(DB_PATH, "SELECT ino FROM dirents WHERE name = 'corrupt_test';");
+    assert!(!ino.is_empty(), "could not find ino for corrupt_test in dirents");
+    sqlite3_query(DB_PATH, &format!("DELETE FROM inodes WHERE ino = {ino}; DELETE FROM dirents WHERE ino = {ino};"));
+    let orphaned_chunks = sqlite3_query(DB_PATH, &format!("SELECT COUNT(*) FROM chunks WHERE ino = {ino};"));
+    assert_eq!(orphaned_chunks, "2", "corruption setup should leave the chunk rows behind");
+
+    let check_output = run_cli(&["check", "--db-path", DB_PATH]);
+    let check_stdout = String::from_utf8_lossy(&check_output.stdout).to_string();
+    assert!(check_stdout.contains("orphaned_chunks"), "check should report orphaned_chunks, got: {check_stdout}");
+
+    let repair_output = run_cli(&["check", "--db-path", DB_PATH, "--repair"]);
+    let repair_stdout = String::from_utf8_lossy(&repair_output.stdout).to_string();
+    assert!(repair_stdout.contains("issue(s) repaired"), "repair should report issues fixed, got: {repair_stdout}");
+
+    let recheck_output = run_cli(&["check", "--db-path", DB_PATH]);
+    let recheck_stdout = String::from_utf8_lossy(&recheck_output.stdout).trim().to_string();
+    assert!(recheck_stdout.contains("no inconsistencies found"), "a second check should find nothing left, got: {recheck_stdout}");
+
+    let remaining_chunks = sqlite3_query(DB_PATH, &format!("SELECT COUNT(*) FROM chunks WHERE ino = {ino};"));
+    assert_eq!(remaining_chunks, "0", "repair should have freed the orphaned chunk rows");
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let mount_result = (|| -> Result<(), String> {
+        let mut buf = Vec::new();
+        File::open(&control_path).map_err(|e| format!("open control_file: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("read control_file: {e}"))?;
+        if buf != control_data {
+            return Err("control_file content changed across repair".to_string());
+        }
+        let entries: Vec<_> = read_dir(MOUNTPOINT).map_err(|e| format!("readdir: {e}"))?.filter_map(|e| e.ok()).collect();
+        if entries.iter().any(|e| e.file_name() == "corrupt_test") {
+            return Err("corrupt_test still listed after repair removed its inode".to_string());
+        }
+        Ok(())
+    })();
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    let _ = fs::remove_file(DB_PATH);
+
+    mount_result.expect("tree was not mountable/consistent after repair");
+}
+
+/// Exercises the WAL journal mode and read-connection pool added for
+/// concurrent FUSE workers: one writer repeatedly replaces a file's entire
+/// content with a single-byte-repeated buffer tagged by an increasing
+/// version number, while several reader threads hammer the same file in
+/// parallel. A read that observes a byte mismatch within itself would mean a
+/// torn read across two versions, and any I/O error would mean a "database
+/// is locked" failure leaked through to a FUSE caller; neither should ever
+/// happen with WAL plus a busy timeout.
+#[test]
+fn concurrent_readers_with_writer() {
+    const DB_PATH: &str = "test-sqlite-chunked-concurrency.db";
+    const NUM_READERS: usize = 8;
+    const VERSIONS: u8 = 40;
+    const BUF_SIZE: usize = 4096;
+    clean_setup(Some(DB_PATH));
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+
+    let result = (|| -> Result<(), String> {
+        File::create(TEST_FILE).map_err(|e| format!("create: {e}"))?
+            .write_all(&vec![0u8; BUF_SIZE]).map_err(|e| format!("initial write: {e}"))?;
+
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let reader_errors: Arc<std::sync::Mutex<Vec<String>>> = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let mut readers = Vec::new();
+        for _ in 0..NUM_READERS {
+            let stop = stop.clone();
+            let reader_errors = reader_errors.clone();
+            readers.push(thread::spawn(move || {
+                while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                    let outcome = (|| -> Result<(), String> {
+                        let mut buf = Vec::new();
+                        File::open(TEST_FILE).map_err(|e| format!("reader open: {e}"))?
+                            .read_to_end(&mut buf).map_err(|e| format!("reader read: {e}"))?;
+                        if buf.len() != BUF_SIZE {
+                            return Err(format!("reader saw {} bytes, expected {}", buf.len(), BUF_SIZE));
+                        }
+                        if !buf.iter().all(|&b| b == buf[0]) {
+                            return Err(format!("reader saw a torn mix of versions: first byte {}, not uniform", buf[0]));
+                        }
+                        Ok(())
+                    })();
+                    if let Err(e) = outcome {
+                        reader_errors.lock().unwrap().push(e);
+                        return;
+                    }
+                }
+            }));
+        }
+
+        let writer_result = (|| -> Result<(), String> {
+            for version in 1..=VERSIONS {
+                let mut file = OpenOptions::new().write(true).open(TEST_FILE).map_err(|e| format!("writer open v{version}: {e}"))?;
+                file.write_all(&vec![version; BUF_SIZE]).map_err(|e| format!("writer write v{version}: {e}"))?;
+            }
+            Ok(())
+        })();
+
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        for r in readers {
+            let _ = r.join();
+        }
+
+        writer_result?;
+        let errors = reader_errors.lock().unwrap();
+        if !errors.is_empty() {
+            return Err(format!("{} reader error(s), e.g.: {}", errors.len(), errors[0]));
+        }
+        fs::remove_file(TEST_FILE).map_err(|e| format!("remove: {e}"))?;
+        Ok(())
+    })();
+
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    let _ = fs::remove_file(DB_PATH);
+
+    result.expect("concurrent readers alongside a writer hit a lock error or saw corruption");
+}
+
+/// Proves `blobs` is a true single-instance store: two files written with
+/// identical content intern to exactly one blob row, and deleting one file
+/// only drops the reference count rather than the blob, leaving the other
+/// file's data intact.
+#[test]
+fn duplicate_files_share_one_blob() {
+    const DB_PATH: &str = "test-sqlite-chunked-dedup.db";
+    clean_setup(Some(DB_PATH));
+
+    let data = vec![b'd'; 4096];
+    let path_a = format!("{}/dup_a", MOUNTPOINT);
+    let path_b = format!("{}/dup_b", MOUNTPOINT);
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let write_result = (|| -> Result<(), String> {
+        File::create(&path_a).map_err(|e| format!("create a: {e}"))?.write_all(&data).map_err(|e| format!("write a: {e}"))?;
+        File::create(&path_b).map_err(|e| format!("create b: {e}"))?.write_all(&data).map_err(|e| format!("write b: {e}"))?;
+        Ok(())
+    })();
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    write_result.expect("writing the duplicate files failed");
+
+    let blob_count = sqlite3_query(DB_PATH, "SELECT COUNT(*) FROM blobs;");
+    assert_eq!(blob_count, "1", "two identical files should intern to exactly one blob");
+    let refcount = sqlite3_query(DB_PATH, "SELECT refcount FROM blobs;");
+    assert_eq!(refcount, "2", "a blob shared by two files should have refcount 2");
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let delete_a_result = fs::remove_file(&path_a).map_err(|e| format!("remove a: {e}"));
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    delete_a_result.expect("deleting the first duplicate failed");
+
+    let blob_count_after_one_delete = sqlite3_query(DB_PATH, "SELECT COUNT(*) FROM blobs;");
+    assert_eq!(blob_count_after_one_delete, "1", "the blob should survive while the other file still references it");
+    let refcount_after_one_delete = sqlite3_query(DB_PATH, "SELECT refcount FROM blobs;");
+    assert_eq!(refcount_after_one_delete, "1", "refcount should drop to 1, not vanish");
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let check_b_result = (|| -> Result<(), String> {
+        let mut buf = Vec::new();
+        File::open(&path_b).map_err(|e| format!("open b: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("read b: {e}"))?;
+        if buf != data {
+            return Err("surviving file's content changed after the duplicate was deleted".to_string());
+        }
+        fs::remove_file(&path_b).map_err(|e| format!("remove b: {e}"))?;
+        Ok(())
+    })();
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    check_b_result.expect("surviving duplicate was not intact");
+
+    let blob_count_after_both_deleted = sqlite3_query(DB_PATH, "SELECT COUNT(*) FROM blobs;");
+    assert_eq!(blob_count_after_both_deleted, "0", "the blob should be freed once its last reference is gone");
+
+    let _ = fs::remove_file(DB_PATH);
+}
+
+/// Creates, reads, and unlinks both link types `sqlite_chunked` supports: a
+/// hard link sharing the target's inode (`nlink` bumps to 2 and the target's
+/// data survives unlinking the link), and a symlink pointing at a target
+/// path (read back via `readlink`, and removable without touching the
+/// target).
+#[test]
+fn hardlink_symlink_create_read_unlink() {
+    const DB_PATH: &str = "test-sqlite-chunked-links.db";
+    clean_setup(Some(DB_PATH));
+
+    let hardlink_target = format!("{}/hardlink_target", MOUNTPOINT);
+    let hardlink_name = format!("{}/hardlink_name", MOUNTPOINT);
+    let symlink_target = format!("{}/symlink_target", MOUNTPOINT);
+    let symlink_name = format!("{}/symlink_name", MOUNTPOINT);
+    let hardlink_data = b"hard link test content";
+    let symlink_data = b"symlink test content";
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let result = (|| -> Result<(), String> {
+        use std::os::unix::fs::MetadataExt;
+
+        // Hard link: create, read through both names, check nlink, unlink
+        // one name and confirm the data survives under the other.
+        File::create(&hardlink_target).map_err(|e| format!("create hardlink target: {e}"))?
+            .write_all(hardlink_data).map_err(|e| format!("write hardlink target: {e}"))?;
+        fs::hard_link(&hardlink_target, &hardlink_name).map_err(|e| format!("hard_link: {e}"))?;
+        let mut buf = Vec::new();
+        File::open(&hardlink_name).map_err(|e| format!("open hardlink_name: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("read hardlink_name: {e}"))?;
+        if buf != hardlink_data {
+            return Err("reading through the hard link name did not match the target's content".to_string());
+        }
+        let nlink = metadata(&hardlink_target).map_err(|e| format!("stat target: {e}"))?.nlink();
+        if nlink != 2 {
+            return Err(format!("expected nlink 2 after hard_link, got {nlink}"));
+        }
+        fs::remove_file(&hardlink_name).map_err(|e| format!("unlink hardlink_name: {e}"))?;
+        let mut buf = Vec::new();
+        File::open(&hardlink_target).map_err(|e| format!("reopen target: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("reread target: {e}"))?;
+        if buf != hardlink_data {
+            return Err("target content did not survive unlinking the hard link name".to_string());
+        }
+        let nlink = metadata(&hardlink_target).map_err(|e| format!("restat target: {e}"))?.nlink();
+        if nlink != 1 {
+            return Err(format!("expected nlink 1 after unlinking the hard link name, got {nlink}"));
+        }
+        fs::remove_file(&hardlink_target).map_err(|e| format!("remove target: {e}"))?;
+
+        // Symlink: create, follow it, unlink it, and confirm the target
+        // (addressed directly) is untouched.
+        File::create(&symlink_target).map_err(|e| format!("create symlink target: {e}"))?
+            .write_all(symlink_data).map_err(|e| format!("write symlink target: {e}"))?;
+        symlink(&symlink_target, &symlink_name).map_err(|e| format!("symlink: {e}"))?;
+        let followed = fs::read_link(&symlink_name).map_err(|e| format!("read_link: {e}"))?;
+        if followed != std::path::Path::new(&symlink_target) {
+            return Err("read_link did not return the symlink's target path".to_string());
+        }
+        let mut buf = Vec::new();
+        File::open(&symlink_name).map_err(|e| format!("open through symlink: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("read through symlink: {e}"))?;
+        if buf != symlink_data {
+            return Err("reading through the symlink did not match the target's content".to_string());
+        }
+        fs::remove_file(&symlink_name).map_err(|e| format!("remove symlink: {e}"))?;
+        let mut buf = Vec::new();
+        File::open(&symlink_target).map_err(|e| format!("reopen symlink target: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("reread symlink target: {e}"))?;
+        if buf != symlink_data {
+            return Err("symlink target content did not survive unlinking the symlink".to_string());
+        }
+        fs::remove_file(&symlink_target).map_err(|e| format!("remove symlink target: {e}"))?;
+        Ok(())
+    })();
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    let _ = fs::remove_file(DB_PATH);
+
+    result.expect("hard link / symlink create-read-unlink failed");
+}
+
+/// Exercises the incremental BLOB I/O path with reads and writes that stay
+/// strictly inside one chunk: a sub-range read that never crosses the
+/// `chunk_size` boundary, and a write that modifies bytes in a chunk's
+/// interior without changing its length.
+#[test]
+fn sub_chunk_read_write_interior() {
+    const DB_PATH: &str = "test-sqlite-chunked-subchunk.db";
+    const CHUNK_SIZE: usize = 4096;
+    clean_setup(Some(DB_PATH));
+
+    let mut data: Vec<u8> = (0..CHUNK_SIZE * 2).map(|i| (i % 251) as u8).collect();
+    let path = format!("{}/subchunk_test", MOUNTPOINT);
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let result = (|| -> Result<(), String> {
+        // A single whole-file write would take the CDC dispatch path (see
+        // `write` in sqlite_chunked.rs) and never touch the fixed-offset
+        // `chunks` table this test means to exercise. Writing each chunk
+        // separately forces the second call through the CDC-to-fixed-offset
+        // conversion, landing both chunks in `chunks` as full-length, raw,
+        // singly-referenced blobs — exactly what the later interior write
+        // needs to hit `write_file_data_inner`'s `blob_open` fast path rather
+        // than falling back to its read-modify-rewrite path.
+        let mut setup_file = File::create(&path).map_err(|e| format!("create: {e}"))?;
+        setup_file.write_all(&data[..CHUNK_SIZE]).map_err(|e| format!("write (1st chunk): {e}"))?;
+        setup_file.write_all(&data[CHUNK_SIZE..]).map_err(|e| format!("write (2nd chunk): {e}"))?;
+        drop(setup_file);
+
+        // Sub-chunk read entirely inside the first chunk.
+        let mut file = File::open(&path).map_err(|e| format!("open for sub-read: {e}"))?;
+        file.seek(std::io::SeekFrom::Start(100)).map_err(|e| format!("seek sub-read: {e}"))?;
+        let mut partial = vec![0u8; 200];
+        file.read_exact(&mut partial).map_err(|e| format!("sub-chunk read: {e}"))?;
+        if partial != data[100..300] {
+            return Err("sub-chunk read within the first chunk mismatched".to_string());
+        }
+        drop(file);
+
+        // Sub-chunk read entirely inside the second chunk.
+        let mut file = File::open(&path).map_err(|e| format!("reopen for sub-read 2: {e}"))?;
+        let start = CHUNK_SIZE + 500;
+        file.seek(std::io::SeekFrom::Start(start as u64)).map_err(|e| format!("seek sub-read 2: {e}"))?;
+        let mut partial = vec![0u8; 300];
+        file.read_exact(&mut partial).map_err(|e| format!("sub-chunk read 2: {e}"))?;
+        if partial != data[start..start + 300] {
+            return Err("sub-chunk read within the second chunk mismatched".to_string());
+        }
+        drop(file);
+
+        // Write that modifies only a chunk's interior, leaving its length
+        // and every byte outside the patched range unchanged.
+        let patch_start = 1000;
+        let patch: Vec<u8> = vec![0xEE; 64];
+        let mut file = OpenOptions::new().write(true).open(&path).map_err(|e| format!("open for interior write: {e}"))?;
+        file.seek(std::io::SeekFrom::Start(patch_start as u64)).map_err(|e| format!("seek interior write: {e}"))?;
+        file.write_all(&patch).map_err(|e| format!("interior write: {e}"))?;
+        drop(file);
+        data[patch_start..patch_start + patch.len()].copy_from_slice(&patch);
+
+        let mut buf = Vec::new();
+        File::open(&path).map_err(|e| format!("reopen after interior write: {e}"))?.read_to_end(&mut buf).map_err(|e| format!("read after interior write: {e}"))?;
+        if buf.len() != data.len() {
+            return Err(format!("file length changed after interior write: got {}, expected {}", buf.len(), data.len()));
+        }
+        if buf != data {
+            return Err("content mismatch after interior write, outside the patched range".to_string());
+        }
+
+        fs::remove_file(&path).map_err(|e| format!("remove: {e}"))?;
+        Ok(())
+    })();
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    let _ = fs::remove_file(DB_PATH);
+
+    result.expect("sub-chunk read/write interior test failed");
+}
+
+/// Writes a file, snapshots, writes a second file, and checks that
+/// `--export-since <era>` lists exactly the second write's chunk and none of
+/// the first: the pre-snapshot chunk is tagged with an era below the
+/// snapshot and must not appear, while the post-snapshot chunk is tagged at
+/// or above it and must.
+#[test]
+fn writeset_diff_lists_only_post_snapshot_chunks() {
+    const DB_PATH: &str = "test-sqlite-chunked-writeset.db";
+    clean_setup(Some(DB_PATH));
+
+    let pre_snapshot_path = format!("{}/pre_snapshot", MOUNTPOINT);
+    let post_snapshot_path = format!("{}/post_snapshot", MOUNTPOINT);
+    // Distinct, chunk-size-disjoint lengths so the writeset's "bytes" column
+    // unambiguously identifies which write produced which row.
+    let pre_snapshot_data = vec![b'p'; 100];
+    let post_snapshot_data = vec![b'q'; 222];
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let write_result = File::create(&pre_snapshot_path)
+        .and_then(|mut f| f.write_all(&pre_snapshot_data))
+        .map_err(|e| format!("pre-snapshot write: {e}"));
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    write_result.expect("pre-snapshot write failed");
+
+    let snapshot_output = run_cli(&["snapshot", "--db-path", DB_PATH, "--snapshot"]);
+    let stdout = String::from_utf8_lossy(&snapshot_output.stdout).trim().to_string();
+    let era: u64 = stdout.rsplit(' ').next().and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| panic!("could not parse snapshot era from: {stdout:?}"));
+
+    let mut child = run_fuse_with_provider("sqlite_chunked", Some(DB_PATH));
+    wait_for_mount();
+    let write_result = File::create(&post_snapshot_path)
+        .and_then(|mut f| f.write_all(&post_snapshot_data))
+        .map_err(|e| format!("post-snapshot write: {e}"));
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    write_result.expect("post-snapshot write failed");
+
+    let export_output = run_cli(&["snapshot", "--db-path", DB_PATH, "--export-since", &era.to_string()]);
+    let export_stdout = String::from_utf8_lossy(&export_output.stdout).to_string();
+    let _ = fs::remove_file(DB_PATH);
+
+    assert!(
+        export_stdout.contains(&format!("1 record(s) changed since era {era}")),
+        "expected exactly the one post-snapshot chunk in the diff, got: {export_stdout}"
+    );
+    assert!(
+        export_stdout.contains(&post_snapshot_data.len().to_string()),
+        "diff should list the post-snapshot write's byte length, got: {export_stdout}"
+    );
+    assert!(
+        !export_stdout.lines().any(|l| l.contains("chunk") && l.contains(&pre_snapshot_data.len().to_string())),
+        "diff should not list the pre-snapshot write's chunk, got: {export_stdout}"
+    );
+}
+
+/// Writes to a `0o6755` file as a non-root user and asserts the resulting
+/// mode is `0o0755`: `clear_suid_sgid` strips `S_ISUID` unconditionally for
+/// a non-root writer, and `S_ISGID` too since group-execute is set. Mounts
+/// (and therefore issues every FUSE call) as the `nobody` user via
+/// `setpriv`, rather than root, so `req_uid` is genuinely non-zero; skips if
+/// that isn't possible in this environment.
+#[test]
+fn write_clears_setuid_setgid_as_non_root() {
+    const DB_PATH: &str = "test-sqlite-chunked-suid.db";
+    const NOBODY_UID: u32 = 65534;
+    const NOBODY_GID: u32 = 65534;
+    clean_setup(Some(DB_PATH));
+
+    File::create(DB_PATH).expect("touch db file");
+    if !chown_path(DB_PATH, NOBODY_UID, NOBODY_GID) || !chown_path(MOUNTPOINT, NOBODY_UID, NOBODY_GID) {
+        println!("skipping write_clears_setuid_setgid_as_non_root: could not chown to uid {NOBODY_UID} in this environment");
+        let _ = fs::remove_file(DB_PATH);
+        return;
+    }
+
+    let child = run_fuse_with_provider_as("sqlite_chunked", Some(DB_PATH), NOBODY_UID, NOBODY_GID);
+    let Some(mut child) = child else {
+        println!("skipping write_clears_setuid_setgid_as_non_root: setpriv unavailable in this environment");
+        let _ = fs::remove_file(DB_PATH);
+        return;
+    };
+    wait_for_mount();
+
+    let result = (|| -> Result<(), String> {
+        use std::os::unix::fs::PermissionsExt;
+        let path = format!("{}/suid_test", MOUNTPOINT);
+        File::create(&path).map_err(|e| format!("create: {e}"))?.write_all(b"payload").map_err(|e| format!("write: {e}"))?;
+        fs::set_permissions(&path, std::fs::Permissions::from_mode(0o6755)).map_err(|e| format!("chmod: {e}"))?;
+
+        let mut file = OpenOptions::new().write(true).open(&path).map_err(|e| format!("reopen for non-root write: {e}"))?;
+        file.write_all(b"x").map_err(|e| format!("non-root write: {e}"))?;
+        drop(file);
+
+        let mode = metadata(&path).map_err(|e| format!("stat: {e}"))?.permissions().mode() & 0o7777;
+        if mode != 0o0755 {
+            return Err(format!("expected mode 0o0755 after a non-root write to a 0o6755 file, got {mode:o}"));
+        }
+        fs::remove_file(&path).map_err(|e| format!("remove: {e}"))?;
+        Ok(())
+    })();
+
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT); }
+    let _ = child.wait();
+    wait_for_unmount();
+    let _ = fs::remove_file(DB_PATH);
+    let _ = chown_path(MOUNTPOINT, 0, 0);
+
+    result.expect("a non-root write to a 0o6755 file did not clear its setuid/setgid bits");
+}